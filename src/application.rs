@@ -7,19 +7,29 @@
 //
 
 use crate::config::{APP_ID, VERSION};
+use crate::history::History;
+use crate::recent::RecentFiles;
+use crate::session::Session;
+use crate::settings::Settings;
 use crate::window::Window;
 use gtk::prelude::*;
 use gtk::{gio, glib, subclass::prelude::*};
-use tracing::debug;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use tracing::{debug, error};
 
 mod imp {
     use super::*;
     use glib::WeakRef;
-    use std::cell::OnceCell;
+    use std::cell::{OnceCell, RefCell};
 
     #[derive(Debug, Default)]
     pub struct TemplateFiller {
         pub window: OnceCell<WeakRef<Window>>,
+        pub settings: RefCell<Settings>,
+        pub recent: RefCell<RecentFiles>,
+        pub history: RefCell<History>,
+        pub session: Session,
     }
 
     #[glib::object_subclass]
@@ -35,13 +45,16 @@ mod imp {
         fn activate(&self) {
             debug!("GtkApplication<TemplateFiller>::activate()");
             self.parent_activate();
-            self.obj().present_main_window(None);
+            self.obj().present_main_window(&[]);
         }
 
         fn startup(&self) {
             debug!("GtkApplication<TemplateFiller>::startup()");
             self.parent_startup();
             let app = self.obj();
+            self.settings.replace(Settings::load());
+            self.recent.replace(RecentFiles::load());
+            self.history.replace(History::load());
             gtk::Window::set_default_icon_name(APP_ID);
             app.setup_gactions();
             app.setup_accels();
@@ -49,8 +62,7 @@ mod imp {
 
         fn open(&self, files: &[gio::File], _hint: &str) {
             debug!("GtkApplication<TemplateFiller>::open()");
-            let file = files.first().cloned();
-            self.obj().present_main_window(file)
+            self.obj().present_main_window(files)
         }
     }
 
@@ -68,21 +80,192 @@ impl TemplateFiller {
         self.set_accels_for_action("app.quit", &["<Control>q"]);
         self.set_accels_for_action("win.show-help-overlay", &["<primary>question"]);
         self.set_accels_for_action("win.open-document", &["<primary>o"]);
+        self.set_accels_for_action("win.open-directory", &["<primary><shift>o"]);
         self.set_accels_for_action("win.save-document", &["<primary>s"]);
+        self.set_accels_for_action("win.load-values", &["<primary>l"]);
+        self.set_accels_for_action("win.export-values", &["<primary>e"]);
         self.set_accels_for_action("win.close-document", &["<primary>w"]);
     }
 
     fn setup_gactions(&self) {
         let action_quit = gio::ActionEntry::builder("quit")
-            .activate(move |app: &Self, _, _| app.quit())
+            .activate(move |app: &Self, _, _| app.close_windows())
             .build();
         let action_about = gio::ActionEntry::builder("about")
             .activate(move |app: &Self, _, _| app.show_about_dialog())
             .build();
-        self.add_action_entries([action_quit, action_about]);
+        let action_strict_mode = gio::ActionEntry::builder("strict-mode")
+            .state(self.settings().strict.to_variant())
+            .activate(move |app: &Self, action, _| {
+                let strict = !action
+                    .state()
+                    .and_then(|state| state.get::<bool>())
+                    .unwrap_or_default();
+                action.set_state(&strict.to_variant());
+                app.set_strict(strict);
+            })
+            .build();
+        let action_import_environment = gio::ActionEntry::builder("import-environment")
+            .state(self.settings().import_env.to_variant())
+            .activate(move |app: &Self, action, _| {
+                let import = !action
+                    .state()
+                    .and_then(|state| state.get::<bool>())
+                    .unwrap_or_default();
+                action.set_state(&import.to_variant());
+                app.set_import_env(import);
+            })
+            .build();
+        let action_open_recent = gio::ActionEntry::builder("open-recent")
+            .parameter_type(Some(&PathBuf::static_variant_type()))
+            .activate(move |app: &Self, _, parameter| {
+                if let Some(path) = parameter.and_then(PathBuf::from_variant) {
+                    debug!("Opening recent {}", path.display());
+                    app.present_main_window(&[gio::File::for_path(path)]);
+                }
+            })
+            .build();
+        self.add_action_entries([
+            action_quit,
+            action_about,
+            action_strict_mode,
+            action_import_environment,
+            action_open_recent,
+        ]);
+    }
+
+    /// Return the preferences of the user.
+    pub fn settings(&self) -> Settings {
+        self.imp().settings.borrow().clone()
+    }
+
+    /// Return the values shared by the variables of the open templates.
+    pub fn session(&self) -> &Session {
+        &self.imp().session
+    }
+
+    /// Enable or disable strict rendering in every window, saving the choice.
+    fn set_strict(&self, strict: bool) {
+        debug!("Strict mode: {}", strict);
+        let settings = {
+            let mut settings = self.imp().settings.borrow_mut();
+            settings.strict = strict;
+            settings.clone()
+        };
+        if let Err(error) = settings.save() {
+            error!("Failed to save settings: {}", error);
+        }
+        for window in self.windows() {
+            if let Some(window) = window.downcast_ref::<Window>() {
+                window.set_strict(strict);
+            }
+        }
+    }
+
+    /// Return `true` if the values used for the template at `path` are remembered.
+    pub fn remembers_values(&self, path: &Path) -> bool {
+        !self
+            .imp()
+            .settings
+            .borrow()
+            .private_templates
+            .contains(&History::key(path))
+    }
+
+    /// Remember the values used for the template at `path`, or forget them, saving the choice.
+    pub fn set_remember_values(&self, path: &Path, remember: bool) {
+        debug!("Remember values of {}: {}", path.display(), remember);
+        let settings = {
+            let mut settings = self.imp().settings.borrow_mut();
+            if remember {
+                settings.private_templates.remove(&History::key(path));
+            } else {
+                settings.private_templates.insert(History::key(path));
+            }
+            settings.clone()
+        };
+        if let Err(error) = settings.save() {
+            error!("Failed to save settings: {}", error);
+        }
+        if !remember {
+            self.forget_values(path);
+        }
+    }
+
+    /// Set the variables from the environment when opening a template, saving the choice.
+    fn set_import_env(&self, import: bool) {
+        debug!("Import from environment: {}", import);
+        let settings = {
+            let mut settings = self.imp().settings.borrow_mut();
+            settings.import_env = import;
+            settings.clone()
+        };
+        if let Err(error) = settings.save() {
+            error!("Failed to save settings: {}", error);
+        }
+    }
+
+    /// Return the values last used for the template at `path`, if remembered.
+    pub fn saved_values(&self, path: &Path) -> Option<Value> {
+        if !self.remembers_values(path) {
+            return None;
+        }
+        self.imp().history.borrow().get(path).cloned()
+    }
+
+    /// Remember `data` as the values used for the template at `path`, if enabled.
+    pub fn save_values(&self, path: &Path, data: Value) {
+        if self.remembers_values(path) {
+            self.imp().history.borrow_mut().set(path, data);
+            self.history_changed();
+        }
+    }
+
+    /// Forget the values used for the template at `path`.
+    pub fn forget_values(&self, path: &Path) {
+        self.imp().history.borrow_mut().remove(path);
+        self.history_changed();
+    }
+
+    fn history_changed(&self) {
+        if let Err(error) = self.imp().history.borrow().save() {
+            error!("Failed to save values: {}", error);
+        }
+    }
+
+    /// Return the templates opened recently, most recent first.
+    pub fn recent_files(&self) -> Vec<PathBuf> {
+        self.imp().recent.borrow().files().to_vec()
     }
 
-    fn present_main_window(&self, file: Option<gio::File>) {
+    /// Remember that the template at `path` was opened.
+    pub fn add_recent_file(&self, path: &Path) {
+        self.imp().recent.borrow_mut().add(path);
+        self.recent_files_changed();
+    }
+
+    /// Forget the recent templates which no longer exist.
+    pub fn prune_recent_files(&self) {
+        if self.imp().recent.borrow_mut().prune() {
+            self.recent_files_changed();
+        }
+    }
+
+    /// Save the recent templates and show them in every window.
+    fn recent_files_changed(&self) {
+        if let Err(error) = self.imp().recent.borrow().save() {
+            error!("Failed to save recent files: {}", error);
+        }
+        let files = self.recent_files();
+        for window in self.windows() {
+            if let Some(window) = window.downcast_ref::<Window>() {
+                window.set_recent_files(&files);
+            }
+        }
+    }
+
+    /// Present the main window, opening each of `files` in a tab.
+    fn present_main_window(&self, files: &[gio::File]) {
         let window = if let Some(window) = self.active_window() {
             window
         } else {
@@ -90,11 +273,20 @@ impl TemplateFiller {
             window.upcast()
         };
         window.present();
-        if let Some(file) = file {
-            let window = window
-                .downcast_ref::<Window>()
-                .expect("Widget must be a Window");
-            window.open_document(file)
+        let window = window
+            .downcast_ref::<Window>()
+            .expect("Widget must be a Window");
+        for file in files {
+            window.open_document(file.clone())
+        }
+    }
+
+    /// Close the windows, which ask to confirm if they have unsaved changes.
+    ///
+    /// The application quits once the last window is closed.
+    fn close_windows(&self) {
+        for window in self.windows() {
+            window.close();
         }
     }
 
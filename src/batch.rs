@@ -0,0 +1,370 @@
+//
+// This file is part of template-filler
+//
+// SPDX-FileCopyrightText: © 2024 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+use crate::data;
+use crate::document::{self, Document, Error, VariableNode, TEMPLATE_SUFFIX};
+use crate::helpers;
+use handlebars::Handlebars;
+use heck::ToSnakeCase;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Values of one output of a batch, by column.
+pub type Record = Map<String, Value>;
+
+/// Name of the value holding the number of the record, for the output file names.
+const ROW_VARIABLE: &str = "row";
+
+/// Load the records from a CSV file with a header row, or a JSON or YAML array of objects.
+pub fn load_records<P: AsRef<Path>>(path: P) -> Result<Vec<Record>, Error> {
+    let path = path.as_ref();
+    if path.extension().is_some_and(|extension| extension == "csv") {
+        let mut reader = csv::Reader::from_path(path)?;
+        let headers = reader.headers()?.clone();
+        let mut records = Vec::new();
+        for row in reader.records() {
+            let row = row?;
+            let record = headers
+                .iter()
+                .zip(row.iter())
+                .map(|(column, value)| (column.to_string(), Value::from(value)))
+                .collect();
+            records.push(record);
+        }
+        return Ok(records);
+    }
+    match data::load(path)? {
+        Value::Array(items) => items
+            .into_iter()
+            .map(|item| match item {
+                Value::Object(record) => Ok(record),
+                _ => Err(Error::InvalidRecords(path.into())),
+            })
+            .collect(),
+        _ => Err(Error::InvalidRecords(path.into())),
+    }
+}
+
+/// Rendering of a template once per record, into files named after a pattern.
+///
+/// The variables holding a single value, outside of lists, are mapped to the
+/// columns of the records. The others keep their default values.
+#[derive(Debug, Clone)]
+pub struct Batch {
+    fields: Vec<VariableNode>,
+    records: Vec<Record>,
+    defaults: Value,
+    mapping: BTreeMap<String, String>,
+    pattern: String,
+}
+
+impl Batch {
+    /// Prepare the rendering of `document` with `records`, completed by `defaults`.
+    ///
+    /// The variables are mapped to the columns with the same name, ignoring
+    /// case and separators, and the files are numbered by record.
+    pub fn new(document: &Document, records: Vec<Record>, defaults: Value) -> Self {
+        let mut batch = Self {
+            fields: data::scalar_fields(document.tree())
+                .into_iter()
+                .cloned()
+                .collect(),
+            records,
+            defaults,
+            mapping: BTreeMap::new(),
+            pattern: default_pattern(document.path()),
+        };
+        let columns = batch.columns();
+        for field in &batch.fields {
+            let path = field.path();
+            let name = path.rsplit('.').next().unwrap_or(path);
+            let column = [path, name].iter().find_map(|key| {
+                columns
+                    .iter()
+                    .find(|column| column.to_snake_case() == key.to_snake_case())
+            });
+            if let Some(column) = column {
+                batch.mapping.insert(path.to_string(), column.clone());
+            }
+        }
+        batch
+    }
+
+    /// Return the number of records.
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    /// Return `true` if there is no record.
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Return the dotted paths of the variables which can be mapped to a column.
+    pub fn fields(&self) -> impl Iterator<Item = &str> {
+        self.fields.iter().map(VariableNode::path)
+    }
+
+    /// Return the names of the columns found in the records.
+    pub fn columns(&self) -> Vec<String> {
+        let mut columns: Vec<String> = Vec::new();
+        for column in self.records.iter().flat_map(|record| record.keys()) {
+            if !columns.contains(column) {
+                columns.push(column.clone());
+            }
+        }
+        columns
+    }
+
+    /// Return the column mapped to the variable at `path`, if any.
+    pub fn column(&self, path: &str) -> Option<&str> {
+        self.mapping.get(path).map(String::as_str)
+    }
+
+    /// Map the variable at `path` to `column`, or to none.
+    pub fn set_column(&mut self, path: &str, column: Option<&str>) {
+        match column {
+            Some(column) => self.mapping.insert(path.to_string(), column.to_string()),
+            None => self.mapping.remove(path),
+        };
+    }
+
+    /// Return the pattern of the names of the output files.
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Set the pattern of the names of the output files.
+    ///
+    /// The pattern is a Handlebars template rendered with the values of each
+    /// record, where `row` is the number of the record if not a variable.
+    pub fn set_pattern(&mut self, pattern: &str) {
+        self.pattern = pattern.to_string();
+    }
+
+    /// Return the data to render the template with for the record at `index`.
+    ///
+    /// It fails if the text in a column does not fit the kind of its variable.
+    pub fn data(&self, index: usize) -> Result<Value, Error> {
+        let mut data = self.defaults.clone();
+        let Some(record) = self.records.get(index) else {
+            return Ok(data);
+        };
+        for (path, column) in &self.mapping {
+            let Some(value) = record.get(column) else {
+                continue;
+            };
+            let field = self.fields.iter().find(|field| field.path() == path);
+            let value = match (field, value) {
+                (Some(field), Value::String(text)) => field.convert(text).ok_or_else(|| {
+                    let column = format!("column \"{}\"", column);
+                    Error::InvalidValue(column, field.kind(), text.clone())
+                })?,
+                (_, value) => value.clone(),
+            };
+            document::insert_value(&mut data, path, value);
+        }
+        Ok(data)
+    }
+
+    /// Return the relative path of the output for the record at `index`, given its `data`.
+    pub fn output_name(&self, index: usize, data: &Value) -> Result<PathBuf, Error> {
+        let mut context = data.clone();
+        if let Value::Object(map) = &mut context {
+            map.entry(ROW_VARIABLE)
+                .or_insert_with(|| Value::from(index + 1));
+        }
+        let mut registry = Handlebars::new();
+        registry.set_strict_mode(true);
+        registry.register_escape_fn(handlebars::no_escape);
+        helpers::register(&mut registry);
+        let name = registry.render_template(&self.pattern, &context)?;
+        document::check_file_name(name.trim())
+    }
+
+    /// Render `document` for every record, into the directory at `dir`.
+    pub fn render<P: AsRef<Path>>(self, document: Document, dir: P) -> Rendering {
+        Rendering {
+            batch: self,
+            document,
+            dir: dir.as_ref().into(),
+            next: 0,
+            used: BTreeSet::new(),
+        }
+    }
+}
+
+/// Rendering of the records of a batch, one at a time.
+///
+/// Each item is the index of a record with the path of its output, or the
+/// reason why it could not be rendered.
+#[derive(Debug)]
+pub struct Rendering {
+    batch: Batch,
+    document: Document,
+    dir: PathBuf,
+    next: usize,
+    used: BTreeSet<PathBuf>,
+}
+
+impl Rendering {
+    fn render_record(&mut self, index: usize) -> Result<PathBuf, Error> {
+        let data = self.batch.data(index)?;
+        self.document.validate(&data)?;
+        let name = self.batch.output_name(index, &data)?;
+        if !self.used.insert(name.clone()) {
+            return Err(Error::DuplicateFileName(name));
+        }
+        let path = self.dir.join(name);
+        if self.document.is_directory() {
+            self.document.render_to_dir(&path, &data)?;
+        } else {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            self.document.render_to_file(&path, &data)?;
+        }
+        Ok(path)
+    }
+}
+
+impl Iterator for Rendering {
+    type Item = (usize, Result<PathBuf, Error>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = self.next;
+        if index >= self.batch.len() {
+            return None;
+        }
+        self.next += 1;
+        Some((index, self.render_record(index)))
+    }
+}
+
+/// Return the pattern naming the outputs of the template at `path` after it, numbered.
+fn default_pattern(path: &Path) -> String {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let name = name.strip_suffix(TEMPLATE_SUFFIX).unwrap_or(&name);
+    match name.split_once('.') {
+        Some((stem, extension)) => format!("{}-{{{{{}}}}}.{}", stem, ROW_VARIABLE, extension),
+        None => format!("{}-{{{{{}}}}}", name, ROW_VARIABLE),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const TEMPLATE: &str =
+        "{{first_name}} {{lastName}}, {{#if (gt age 18)}}adult{{/if}}, {{company.name}} {{signature}}";
+    const RECORDS_CSV: &str = "First Name,last-name,Age,name\n\
+                               Jane,Doe,42,ACME\n\
+                               John,Smith,abc,Globex\n\
+                               Jane,Doe,7,Initech\n";
+
+    #[test]
+    fn load_record_formats() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("records.csv");
+        fs::write(&path, RECORDS_CSV).unwrap();
+        let records = load_records(&path).unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[1]["Age"], json!("abc"));
+
+        let path = dir.path().join("records.yaml");
+        fs::write(&path, "- name: Jane\n  age: 42\n- name: John\n").unwrap();
+        let records = load_records(&path).unwrap();
+        assert_eq!(records[0]["age"], json!(42));
+
+        let path = dir.path().join("record.json");
+        fs::write(&path, r#"{"name": "Jane"}"#).unwrap();
+        assert!(matches!(load_records(&path), Err(Error::InvalidRecords(_))));
+    }
+
+    #[test]
+    fn render_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let template = dir.path().join("letter.txt.hbs");
+        fs::write(&template, TEMPLATE).unwrap();
+        let document = Document::open(&template, &[]).unwrap();
+        let records_path = dir.path().join("records.csv");
+        fs::write(&records_path, RECORDS_CSV).unwrap();
+        let records = load_records(&records_path).unwrap();
+
+        let mut batch = Batch::new(&document, records, json!({"signature": "Bob"}));
+        assert_eq!(batch.len(), 3);
+        assert_eq!(batch.column("first_name"), Some("First Name"));
+        assert_eq!(batch.column("lastName"), Some("last-name"));
+        assert_eq!(batch.column("age"), Some("Age"));
+        assert_eq!(batch.column("company.name"), Some("name"));
+        assert_eq!(batch.column("signature"), None);
+        assert_eq!(batch.pattern(), "letter-{{row}}.txt");
+        assert_eq!(batch.data(0).unwrap()["age"], json!(42));
+        let error = batch.data(1).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid value for column \"Age\": expected a number, got \"abc\""
+        );
+
+        let output = dir.path().join("out");
+        let results: Vec<_> = batch.clone().render(document, &output).collect();
+        assert_eq!(results.len(), 3);
+        assert!(results[0].1.is_ok());
+        assert!(matches!(results[1], (1, Err(Error::InvalidValue(..)))));
+        assert!(results[2].1.is_ok());
+        assert_eq!(
+            fs::read_to_string(output.join("letter-1.txt")).unwrap(),
+            "Jane Doe, adult, ACME Bob"
+        );
+
+        batch.set_column("company.name", None);
+        batch.set_column("age", None);
+        assert_eq!(batch.data(0).unwrap()["company"], Value::Null);
+        batch.set_pattern("{{lower first_name}}/{{lastName}}.txt");
+        let document = Document::open(&template, &[]).unwrap();
+        let results: Vec<_> = batch.render(document, &output).collect();
+        assert!(results[0].1.is_ok());
+        assert!(results[1].1.is_ok());
+        assert!(matches!(results[2], (2, Err(Error::DuplicateFileName(_)))));
+        assert!(output.join("jane/Doe.txt").is_file());
+    }
+
+    #[test]
+    fn reject_output_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let template = dir.path().join("site");
+        fs::create_dir(&template).unwrap();
+        fs::write(template.join("index.html.hbs"), "{{title}}").unwrap();
+        let document = Document::open(&template, &[]).unwrap();
+        let records = vec![Record::from_iter([(
+            String::from("title"),
+            Value::from("../Home"),
+        )])];
+        let mut batch = Batch::new(&document, records, json!({}));
+        assert_eq!(batch.pattern(), "site-{{row}}");
+        batch.set_pattern("{{title}}");
+        let data = batch.data(0).unwrap();
+        assert!(matches!(
+            batch.output_name(0, &data),
+            Err(Error::InvalidFileName(_))
+        ));
+        let data = json!({"title": "O'Brien & Sons"});
+        assert_eq!(
+            batch.output_name(0, &data).unwrap(),
+            PathBuf::from("O'Brien & Sons")
+        );
+        batch.set_pattern("{{missing}}");
+        assert!(matches!(batch.output_name(0, &data), Err(Error::Render(_))));
+    }
+}
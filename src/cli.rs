@@ -0,0 +1,267 @@
+//
+// This file is part of template-filler
+//
+// SPDX-FileCopyrightText: © 2024 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+use crate::batch::{self, Batch};
+use crate::data;
+use crate::document::{Document, Error};
+use crate::profiles::Profiles;
+use clap::{Parser, Subcommand};
+use gtk::glib;
+use serde_json::Value;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Exit code for an invalid template.
+const EXIT_TEMPLATE: i32 = 3;
+/// Exit code for invalid data to render a template with.
+const EXIT_DATA: i32 = 4;
+/// Exit code for an I/O error.
+const EXIT_IO: i32 = 5;
+/// Exit code for an error while rendering a template.
+const EXIT_RENDER: i32 = 6;
+
+/// Render a Handlebars template with user input.
+#[derive(Debug, Parser)]
+#[command(
+    name = "template-filler",
+    version,
+    args_conflicts_with_subcommands = true
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// Templates to open in the graphical interface.
+    pub files: Vec<PathBuf>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Render a template without starting the graphical interface.
+    Render {
+        /// Path to the Handlebars template, or to a directory of templates.
+        template: PathBuf,
+        /// Path to a JSON, YAML or TOML file holding the values of the variables.
+        #[arg(short, long)]
+        data: Option<PathBuf>,
+        /// Name of a profile of the template holding the values of the variables.
+        /// The values from the data file take precedence.
+        #[arg(long, value_name = "NAME")]
+        profile: Option<String>,
+        /// Prefix of the environment variables holding the values of the variables,
+        /// e.g. TF_ for TF_CLIENT_NAME to set client_name, or TF_AUTHOR__NAME for author.name.
+        #[arg(long, value_name = "PREFIX")]
+        env_prefix: Option<String>,
+        /// Set the value of the variable at a dotted path, over the other sources.
+        #[arg(long = "set", value_name = "PATH=VALUE", value_parser = parse_pair)]
+        assignments: Vec<(String, String)>,
+        /// Path to the output file, or directory for a directory of templates.
+        /// Standard output if not set.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Fail if a variable is missing from the data.
+        #[arg(long, overrides_with = "no_strict")]
+        strict: bool,
+        /// Leave the variables missing from the data blank, which is the default.
+        #[arg(long, overrides_with = "strict")]
+        no_strict: bool,
+        /// Directory searched for partials, after the one next to the template.
+        #[arg(short, long = "partials", value_name = "DIR")]
+        partials: Vec<PathBuf>,
+        /// Path to a CSV file, or a JSON or YAML array of objects, holding one record
+        /// per output to render into the output directory.
+        #[arg(long, value_name = "FILE", requires = "output")]
+        records: Option<PathBuf>,
+        /// Pattern of the names of the outputs rendered for the records, e.g. "{{name}}.txt".
+        #[arg(long, value_name = "PATTERN", requires = "records")]
+        name: Option<String>,
+        /// Map a variable to a column of the records, instead of the one with the same name.
+        #[arg(
+            long = "map",
+            value_name = "VARIABLE=COLUMN",
+            value_parser = parse_pair,
+            requires = "records"
+        )]
+        mappings: Vec<(String, String)>,
+    },
+}
+
+impl Command {
+    /// Execute the command, returning the exit code of the program.
+    pub fn run(&self) -> glib::ExitCode {
+        let result = match self {
+            Command::Render {
+                template,
+                data,
+                profile,
+                env_prefix,
+                assignments,
+                output,
+                strict,
+                no_strict: _,
+                partials,
+                records,
+                name,
+                mappings,
+            } => {
+                let sources = Sources {
+                    profile: profile.as_deref(),
+                    data: data.as_deref(),
+                    env_prefix: env_prefix.as_deref(),
+                    assignments,
+                };
+                render(
+                    template,
+                    &sources,
+                    output.as_deref(),
+                    *strict,
+                    partials,
+                    records.as_deref().map(|records| BatchOptions {
+                        records,
+                        pattern: name.as_deref(),
+                        mappings,
+                    }),
+                )
+            }
+        };
+        match result {
+            Ok(()) => glib::ExitCode::SUCCESS,
+            Err(error) => {
+                eprintln!("Error: {}", error);
+                glib::ExitCode::from(exit_code(&error))
+            }
+        }
+    }
+}
+
+/// Sources of the values of the variables, from the lowest precedence to the highest.
+struct Sources<'a> {
+    profile: Option<&'a str>,
+    data: Option<&'a Path>,
+    env_prefix: Option<&'a str>,
+    assignments: &'a [(String, String)],
+}
+
+impl Sources<'_> {
+    /// Load the values of the variables of `document`, over their defaults.
+    fn load(&self, document: &Document) -> Result<Value, Error> {
+        let mut values = document.defaults();
+        if let Some(name) = self.profile {
+            data::merge(&mut values, Profiles::new(document.path()).load(name)?);
+        }
+        if let Some(path) = self.data {
+            data::merge(&mut values, data::load(path)?);
+        }
+        if let Some(prefix) = self.env_prefix {
+            data::merge(
+                &mut values,
+                data::from_env(document.tree(), prefix, data::env_vars())?,
+            );
+        }
+        let assignments = self
+            .assignments
+            .iter()
+            .map(|(path, text)| (path.as_str(), text.as_str()));
+        data::merge(
+            &mut values,
+            data::from_assignments(document.tree(), assignments)?,
+        );
+        Ok(values)
+    }
+}
+
+/// Options to render a template once per record.
+struct BatchOptions<'a> {
+    records: &'a Path,
+    pattern: Option<&'a str>,
+    mappings: &'a [(String, String)],
+}
+
+/// Parse a pair given as `NAME=VALUE`, like the mapping of a variable to a column.
+fn parse_pair(text: &str) -> Result<(String, String), String> {
+    text.split_once('=')
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected NAME=VALUE, got {}", text))
+}
+
+fn render(
+    template: &Path,
+    sources: &Sources,
+    output: Option<&Path>,
+    strict: bool,
+    partials_dirs: &[PathBuf],
+    batch: Option<BatchOptions>,
+) -> Result<(), Error> {
+    let mut document = Document::open(template, partials_dirs)?;
+    document.set_strict(strict);
+    let data = sources.load(&document)?;
+    if let (Some(options), Some(output)) = (batch, output) {
+        return render_batch(document, data, options, output);
+    }
+    document.validate(&data)?;
+    match output {
+        Some(path) if document.is_directory() => document.render_to_dir(path, &data),
+        Some(path) => document.render_to_file(path, &data),
+        None => document.render_to_writer(io::stdout().lock(), &data),
+    }
+}
+
+/// Render `document` once per record into the directory `output`, reporting the failures.
+fn render_batch(
+    document: Document,
+    data: Value,
+    options: BatchOptions,
+    output: &Path,
+) -> Result<(), Error> {
+    let records = batch::load_records(options.records)?;
+    let mut batch = Batch::new(&document, records, data);
+    for (variable, column) in options.mappings {
+        let column = Some(column.as_str()).filter(|column| !column.is_empty());
+        batch.set_column(variable, column);
+    }
+    if let Some(pattern) = options.pattern {
+        batch.set_pattern(pattern);
+    }
+    let total = batch.len();
+    let mut failed = 0;
+    for (index, result) in batch.render(document, output) {
+        if let Err(error) = result {
+            eprintln!("Error: record {}: {}", index + 1, error);
+            failed += 1;
+        }
+    }
+    match failed {
+        0 => Ok(()),
+        failed => Err(Error::Batch(failed, total)),
+    }
+}
+
+fn exit_code(error: &Error) -> i32 {
+    match error {
+        Error::InvalidTemplate(_)
+        | Error::Template(_)
+        | Error::Pattern(_)
+        | Error::MissingPartial(_)
+        | Error::Script(_, _) => EXIT_TEMPLATE,
+        Error::Json(_)
+        | Error::Yaml(_)
+        | Error::Toml(_)
+        | Error::TomlSerialize(_)
+        | Error::UnsupportedFormat(_)
+        | Error::Validation(_)
+        | Error::UnknownProfile(_)
+        | Error::DuplicateProfile(_)
+        | Error::Csv(_)
+        | Error::InvalidRecords(_)
+        | Error::InvalidValue(_, _, _) => EXIT_DATA,
+        Error::Io(_) => EXIT_IO,
+        Error::Render(_)
+        | Error::InvalidFileName(_)
+        | Error::DuplicateFileName(_)
+        | Error::Batch(_, _) => EXIT_RENDER,
+    }
+}
@@ -0,0 +1,377 @@
+//
+// This file is part of template-filler
+//
+// SPDX-FileCopyrightText: © 2024 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+use crate::document::{self, Error, VariableKind, VariableNode};
+use heck::ToShoutySnakeCase;
+use serde_json::{Map, Value};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+/// Format of a data file holding the values of variables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl Format {
+    /// Guess the format of the data file at `path` from its extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => Some(Self::Json),
+            Some("yaml") | Some("yml") => Some(Self::Yaml),
+            Some("toml") => Some(Self::Toml),
+            _ => None,
+        }
+    }
+}
+
+/// Load the values of the variables of a template from a data file at `path`.
+///
+/// The file can be in JSON, YAML or TOML format, according to its extension.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Value, Error> {
+    let path = path.as_ref();
+    let format = Format::from_path(path).ok_or_else(|| Error::UnsupportedFormat(path.into()))?;
+    let contents = fs::read_to_string(path)?;
+    let value = match format {
+        Format::Json => serde_json::from_str(&contents)?,
+        Format::Yaml => serde_yaml::from_str(&contents)?,
+        Format::Toml => toml::from_str(&contents)?,
+    };
+    Ok(value)
+}
+
+/// Save the values of the variables of a template to a data file at `path`.
+///
+/// The file is written in JSON, YAML or TOML format, according to its extension.
+pub fn save<P: AsRef<Path>>(path: P, data: &Value) -> Result<(), Error> {
+    let path = path.as_ref();
+    let format = Format::from_path(path).ok_or_else(|| Error::UnsupportedFormat(path.into()))?;
+    let contents = match format {
+        Format::Json => serde_json::to_string_pretty(data)? + "\n",
+        Format::Yaml => serde_yaml::to_string(data)?,
+        // TOML has no null value, so unset values are left out.
+        Format::Toml => toml::to_string_pretty(&without_nulls(data))?,
+    };
+    fs::write(path, contents)?;
+    Ok(())
+}
+
+fn without_nulls(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .filter(|(_, v)| !v.is_null())
+                .map(|(k, v)| (k.clone(), without_nulls(v)))
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(without_nulls).collect()),
+        value => value.clone(),
+    }
+}
+
+/// Convert a single value loaded from a data file to the text of a variable.
+pub fn to_text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// Merge the values of `overlay` into `data`, replacing the ones already set.
+///
+/// Objects are merged recursively, any other value replaces the previous one.
+pub fn merge(data: &mut Value, overlay: Value) {
+    match (data, overlay) {
+        (Value::Object(map), Value::Object(overlay)) => {
+            for (name, value) in overlay {
+                match map.get_mut(&name) {
+                    Some(previous) => merge(previous, value),
+                    None => {
+                        map.insert(name, value);
+                    }
+                }
+            }
+        }
+        (data, overlay) => *data = overlay,
+    }
+}
+
+/// Return the dotted paths and kinds of the variables holding a single value, outside of lists.
+pub fn scalar_fields(nodes: &[VariableNode]) -> Vec<&VariableNode> {
+    let mut fields = Vec::new();
+    collect_scalar_fields(nodes, &mut fields);
+    fields
+}
+
+fn collect_scalar_fields<'a>(nodes: &'a [VariableNode], fields: &mut Vec<&'a VariableNode>) {
+    for node in nodes {
+        match node.kind() {
+            VariableKind::Object => collect_scalar_fields(node.children(), fields),
+            VariableKind::List => {}
+            _ => fields.push(node),
+        }
+    }
+}
+
+/// Return the name of the environment variable holding the value of the variable at `path`.
+///
+/// The segments of the dotted path are in upper snake case, separated by `__`,
+/// after `prefix`: `TF_CLIENT_NAME` for `client_name` and `TF_AUTHOR__NAME` for
+/// `author.name`, with `TF_` as prefix.
+pub fn env_name(prefix: &str, path: &str) -> String {
+    let segments: Vec<String> = path
+        .split('.')
+        .map(|segment| segment.to_shouty_snake_case())
+        .collect();
+    format!("{}{}", prefix, segments.join("__"))
+}
+
+/// Return the environment variables of the process whose name and value are valid Unicode.
+pub fn env_vars() -> impl Iterator<Item = (String, String)> {
+    std::env::vars_os()
+        .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+}
+
+/// Return the values of the variables in `nodes` set by the environment variables `vars`.
+///
+/// Only the variables holding a single value, outside of lists, are looked up,
+/// with the names given by [`env_name`]. It fails if a value does not fit the
+/// kind of its variable.
+pub fn from_env<I>(nodes: &[VariableNode], prefix: &str, vars: I) -> Result<Value, Error>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    let fields: Vec<(String, &VariableNode)> = scalar_fields(nodes)
+        .into_iter()
+        .map(|node| (env_name(prefix, node.path()), node))
+        .collect();
+    let mut data = Value::Object(Map::new());
+    for (name, text) in vars {
+        if let Some((_, node)) = fields.iter().find(|(env_name, _)| *env_name == name) {
+            let value = node.convert(&text).ok_or_else(|| {
+                let path = format!("{} (from {})", node.path(), name);
+                Error::InvalidValue(path, node.kind(), text.clone())
+            })?;
+            document::insert_value(&mut data, node.path(), value);
+        }
+    }
+    Ok(data)
+}
+
+/// Return the values set by pairs of dotted paths and text, converted for the variables in `nodes`.
+///
+/// The text of the paths not matching a variable holding a single value is kept as is.
+/// It fails if the text does not fit the kind of its variable.
+pub fn from_assignments<'a, I>(nodes: &[VariableNode], assignments: I) -> Result<Value, Error>
+where
+    I: IntoIterator<Item = (&'a str, &'a str)>,
+{
+    let fields = scalar_fields(nodes);
+    let mut data = Value::Object(Map::new());
+    for (path, text) in assignments {
+        let node = fields.iter().find(|node| node.path() == path);
+        let value = match node {
+            Some(node) => node.convert(text).ok_or_else(|| {
+                Error::InvalidValue(path.to_string(), node.kind(), text.to_string())
+            })?,
+            None => Value::String(text.to_string()),
+        };
+        document::insert_value(&mut data, path, value);
+    }
+    Ok(data)
+}
+
+/// Return the dotted paths of the values in `data` not matching any variable in `nodes`.
+pub fn unknown_paths(nodes: &[VariableNode], data: &Value) -> Vec<String> {
+    let mut paths = BTreeSet::new();
+    collect_unknown_paths(nodes, data, "", &mut paths);
+    Vec::from_iter(paths)
+}
+
+fn collect_unknown_paths(
+    nodes: &[VariableNode],
+    data: &Value,
+    prefix: &str,
+    paths: &mut BTreeSet<String>,
+) {
+    let Value::Object(map) = data else {
+        return;
+    };
+    for (name, value) in map {
+        let path = if prefix.is_empty() {
+            name.clone()
+        } else {
+            format!("{}.{}", prefix, name)
+        };
+        match nodes.iter().find(|n| n.name() == name) {
+            None => {
+                paths.insert(path);
+            }
+            Some(node) => match (node.kind(), value) {
+                (VariableKind::Object, _) => {
+                    collect_unknown_paths(node.children(), value, &path, paths)
+                }
+                (VariableKind::List, Value::Array(items)) => {
+                    for item in items {
+                        collect_unknown_paths(node.children(), item, &path, paths);
+                    }
+                }
+                _ => {}
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Document;
+    use std::fs::File;
+    use std::io::Write;
+
+    const TEMPLATE: &str = r#"""
+{{author.name}}
+{{#each tags}}{{label}}{{/each}}
+"""#;
+    const DATA_JSON: &str = r#"{"author": {"name": "Jane", "age": 42}, "year": 2025}"#;
+    const DATA_YAML: &str = r#"
+author:
+  name: Jane
+tags:
+  - label: rust
+    color: red
+"#;
+    const DATA_TOML: &str = r#"
+[author]
+name = "Jane"
+"#;
+
+    fn write_file(dir: &Path, name: &str, contents: &str) -> std::path::PathBuf {
+        let path = dir.join(name);
+        let mut file = File::create(&path).unwrap();
+        write!(file, "{}", contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn load_formats() {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, contents) in [
+            ("values.json", DATA_JSON),
+            ("values.yml", DATA_YAML),
+            ("values.toml", DATA_TOML),
+        ] {
+            let path = write_file(dir.path(), name, contents);
+            let data = load(path).unwrap();
+            assert_eq!(data["author"]["name"], Value::from("Jane"));
+        }
+        let path = write_file(dir.path(), "values.txt", DATA_JSON);
+        assert!(matches!(load(path), Err(Error::UnsupportedFormat(_))));
+    }
+
+    #[test]
+    fn report_unknown_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_file(dir.path(), "template.txt.hbs", TEMPLATE);
+        let doc = Document::open(path, &[]).unwrap();
+        let data: Value = serde_json::from_str(DATA_JSON).unwrap();
+        assert_eq!(unknown_paths(doc.tree(), &data), &["author.age", "year"]);
+        let data: Value = serde_yaml::from_str(DATA_YAML).unwrap();
+        assert_eq!(unknown_paths(doc.tree(), &data), &["tags.color"]);
+    }
+
+    #[test]
+    fn save_formats() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = serde_json::json!({
+            "author": {"name": "Jane", "age": 42, "phone": null},
+            "draft": true,
+            "tags": [{"label": "rust"}, {"label": "gtk"}],
+        });
+        for name in ["values.json", "values.yaml", "values.toml"] {
+            let path = dir.path().join(name);
+            save(&path, &data).unwrap();
+            let loaded = load(&path).unwrap();
+            assert_eq!(loaded["author"]["age"], Value::from(42));
+            assert_eq!(loaded["draft"], Value::from(true));
+            assert_eq!(loaded["tags"][1]["label"], Value::from("gtk"));
+        }
+    }
+
+    #[test]
+    fn convert_to_text() {
+        assert_eq!(to_text(&Value::from("Jane")), "Jane");
+        assert_eq!(to_text(&Value::from(42)), "42");
+        assert_eq!(to_text(&Value::from(true)), "true");
+        assert_eq!(to_text(&Value::Null), "");
+    }
+
+    #[test]
+    fn load_overrides() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_file(
+            dir.path(),
+            "overrides.hbs",
+            "{{clientName}} {{author.name}} {{#if (gt count 1)}}s{{/if}} {{#each tags}}{{label}}{{/each}}",
+        );
+        let document = Document::open(&path, &[]).unwrap();
+        assert_eq!(env_name("TF_", "clientName"), "TF_CLIENT_NAME");
+        assert_eq!(env_name("TF_", "author.name"), "TF_AUTHOR__NAME");
+        let vars = [
+            ("TF_CLIENT_NAME", "ACME"),
+            ("TF_AUTHOR__NAME", "Jane"),
+            ("TF_COUNT", "3"),
+            ("TF_TAGS", "rust"),
+            ("HOME", "/home/jane"),
+        ]
+        .map(|(name, value)| (name.to_string(), value.to_string()));
+        assert_eq!(
+            from_env(document.tree(), "TF_", vars).unwrap(),
+            serde_json::json!({"clientName": "ACME", "author": {"name": "Jane"}, "count": 3})
+        );
+        assert_eq!(
+            from_assignments(document.tree(), [("count", "2"), ("author.name", "John")]).unwrap(),
+            serde_json::json!({"count": 2, "author": {"name": "John"}})
+        );
+        let vars = [(String::from("TF_COUNT"), String::from("abc"))];
+        let error = from_env(document.tree(), "TF_", vars).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid value for count (from TF_COUNT): expected a number, got \"abc\""
+        );
+        let error = from_assignments(document.tree(), [("count", "abc")]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid value for count: expected a number, got \"abc\""
+        );
+    }
+
+    #[test]
+    fn merge_values() {
+        let mut data = serde_json::json!({
+            "author": {"name": "Jane", "age": 42},
+            "tags": ["rust", "gtk"],
+        });
+        merge(
+            &mut data,
+            serde_json::json!({"author": {"name": "John"}, "tags": ["cli"], "year": 2025}),
+        );
+        assert_eq!(
+            data,
+            serde_json::json!({
+                "author": {"name": "John", "age": 42},
+                "tags": ["cli"],
+                "year": 2025,
+            })
+        );
+    }
+}
@@ -6,110 +6,1014 @@
 // SPDX-License-Identifier: MIT
 //
 
+use crate::data;
+use crate::helpers::{self, BUILTIN_HELPERS, HELPERS, SCRIPTS_DIR, SCRIPT_SUFFIX};
+use crate::metadata::{self, Metadata, VariableMeta};
+use crate::profiles::PROFILES_SUFFIX;
+use crate::validation::{self, Constraints};
 use handlebars::{
-    template::{Parameter, TemplateElement},
-    Handlebars, Path as JsonPath, PathSeg,
+    template::{BlockParam, DecoratorTemplate, HelperTemplate, Parameter, TemplateElement},
+    Handlebars, Path as JsonPath, PathSeg, Template,
 };
-use std::collections::{BTreeSet, HashMap};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
 use thiserror::Error;
+use tracing::warn;
 
 /// Errors reported when handling a document.
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("Invalid template: {0}")]
     InvalidTemplate(PathBuf),
+    #[error("Invalid JSON data: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Invalid YAML data: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("Invalid TOML data: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("Unsupported TOML data: {0}")]
+    TomlSerialize(#[from] toml::ser::Error),
+    #[error("Unsupported data format: {0}")]
+    UnsupportedFormat(PathBuf),
     #[error("I/O error: {0}")]
     Io(#[from] std::io::Error),
     #[error("Render error: {0}")]
     Render(#[from] handlebars::RenderError),
     #[error("Template error: {0}")]
     Template(#[from] handlebars::TemplateError),
+    #[error("Invalid pattern: {0}")]
+    Pattern(#[from] regex::Error),
+    #[error("Invalid values:\n{0}")]
+    Validation(String),
+    #[error("Partial not found in the partials directories: {0}")]
+    MissingPartial(String),
+    #[error("Invalid script helper {0}: {1}")]
+    Script(String, String),
+    #[error("Profile not found: {0}")]
+    UnknownProfile(String),
+    #[error("Profile already exists: {0}")]
+    DuplicateProfile(String),
+    #[error("Invalid CSV data: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("Expected a list of records: {0}")]
+    InvalidRecords(PathBuf),
+    #[error("Invalid output file name: {0}")]
+    InvalidFileName(String),
+    #[error("Output file name already used: {0}")]
+    DuplicateFileName(PathBuf),
+    #[error("{0} of {1} records failed to render")]
+    Batch(usize, usize),
+    #[error("Invalid value for {0}: expected a {1}, got \"{2}\"")]
+    InvalidValue(String, VariableKind, String),
 }
 
-/// Hold information about a Handlebars template.
-#[derive(Debug)]
+impl Error {
+    /// Return the line and column in the template where the error occurred, if known.
+    pub fn position(&self) -> Option<(usize, usize)> {
+        match self {
+            Error::Template(e) => e.pos(),
+            Error::Render(e) => e.line_no.zip(e.column_no),
+            _ => None,
+        }
+    }
+    /// Return the description of the error, without its position in the template.
+    pub fn summary(&self) -> String {
+        match self {
+            Error::Template(e) => e.reason().to_string(),
+            Error::Render(e) => e.reason().to_string(),
+            e => e.to_string(),
+        }
+    }
+}
+
+/// Kind of a variable found in a template, inferred from its usage.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VariableKind {
+    /// Text value.
+    #[default]
+    String,
+    /// Boolean value, e.g. the condition of an `{{#if}}` block.
+    Boolean,
+    /// Numeric value, e.g. compared with `gt` or `lt`.
+    Number,
+    /// Object with named fields.
+    Object,
+    /// Array of items iterated by an `{{#each}}` block.
+    List,
+    /// Value among a fixed set, e.g. compared with `eq` to string literals.
+    Choice,
+}
+
+impl VariableKind {
+    /// Return `true` if a variable of this kind holds a single value.
+    pub fn is_scalar(&self) -> bool {
+        !matches!(self, Self::Object | Self::List)
+    }
+    /// Convert the `text` of a single value to JSON, failing if it does not fit the kind.
+    ///
+    /// An empty text is a missing number, or `false`.
+    pub fn convert(&self, text: &str) -> Option<Value> {
+        match self {
+            Self::Number if text.is_empty() => Some(Value::Null),
+            Self::Number => Some(self.parse(text)).filter(|value| !value.is_null()),
+            Self::Boolean => ["", "true", "false"]
+                .contains(&text)
+                .then(|| self.parse(text)),
+            _ => Some(self.parse(text)),
+        }
+    }
+    /// Convert the `text` of a single value to JSON, according to the kind.
+    pub fn parse(&self, text: &str) -> Value {
+        match self {
+            Self::Boolean => Value::Bool(text == "true"),
+            Self::Number => text
+                .parse::<i64>()
+                .map(Value::from)
+                .or_else(|_| text.parse::<f64>().map(Value::from))
+                .unwrap_or(Value::Null),
+            _ => Value::String(text.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for VariableKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::String => "string",
+            Self::Boolean => "boolean",
+            Self::Number => "number",
+            Self::Object => "object",
+            Self::List => "list",
+            Self::Choice => "choice",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Variable found in a template, with its nested fields if any.
+///
+/// The children of a list are the fields of its items.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VariableNode {
+    name: String,
+    path: String,
+    kind: VariableKind,
+    choices: Vec<Value>,
+    children: Vec<VariableNode>,
+}
+
+impl VariableNode {
+    /// Build the tree of variables from a set of paths, given what `collector` found.
+    fn build_tree(
+        prefix: &[String],
+        paths: &[Vec<String>],
+        collector: &Collector,
+    ) -> Vec<VariableNode> {
+        let mut nodes = Vec::new();
+        let mut index = 0;
+        while index < paths.len() {
+            let name = &paths[index][prefix.len()];
+            let count = paths[index..]
+                .iter()
+                .take_while(|p| &p[prefix.len()] == name)
+                .count();
+            let mut path = prefix.to_vec();
+            path.push(name.clone());
+            let children: Vec<Vec<String>> = paths[index..index + count]
+                .iter()
+                .filter(|p| p.len() > path.len())
+                .cloned()
+                .collect();
+            let kind = if collector.lists.contains(&path) {
+                VariableKind::List
+            } else if !children.is_empty() {
+                VariableKind::Object
+            } else {
+                match collector.hints.get(&path).copied().unwrap_or_default() {
+                    // A value printed as is is not only a condition.
+                    VariableKind::Boolean if collector.printed.contains(&path) => {
+                        VariableKind::String
+                    }
+                    kind => kind,
+                }
+            };
+            let choices = match kind {
+                VariableKind::Choice => collector.choices.get(&path).cloned().unwrap_or_default(),
+                _ => Vec::new(),
+            };
+            nodes.push(VariableNode {
+                name: name.clone(),
+                path: path.join("."),
+                kind,
+                choices,
+                children: Self::build_tree(&path, &children, collector),
+            });
+            index += count;
+        }
+        nodes
+    }
+    /// Override the kind and choices of the leaf variables with those declared in `metadata`.
+    fn apply_kinds(nodes: &mut [VariableNode], metadata: &Metadata) {
+        for node in nodes {
+            if node.is_leaf() && node.kind.is_scalar() {
+                if let Some(meta) = metadata.get(&node.path) {
+                    if !meta.choices.is_empty() {
+                        node.kind = VariableKind::Choice;
+                        node.choices = meta.choices.clone();
+                    } else if let Some(kind) = meta.kind.filter(VariableKind::is_scalar) {
+                        node.kind = kind;
+                        node.choices.clear();
+                    }
+                }
+            }
+            Self::apply_kinds(&mut node.children, metadata);
+        }
+    }
+    /// Return the name of the variable, relative to its parent.
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+    /// Return the full dotted path of the variable.
+    pub fn path(&self) -> &str {
+        self.path.as_str()
+    }
+    /// Return the kind of the variable.
+    pub fn kind(&self) -> VariableKind {
+        self.kind
+    }
+    /// Return the values allowed for a choice variable.
+    pub fn choices(&self) -> &[Value] {
+        self.choices.as_slice()
+    }
+    /// Convert the `text` of a value of the variable to JSON, failing if it does not fit its kind.
+    ///
+    /// The text of a choice gives the value declared for it, e.g. a number.
+    pub fn convert(&self, text: &str) -> Option<Value> {
+        match self
+            .choices
+            .iter()
+            .find(|choice| data::to_text(choice) == text)
+        {
+            Some(choice) => Some(choice.clone()),
+            None => self.kind.convert(text),
+        }
+    }
+    /// Return the nested fields of the variable.
+    pub fn children(&self) -> &[VariableNode] {
+        self.children.as_slice()
+    }
+    /// Return `true` if the variable has no nested fields.
+    pub fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+    fn collect_leaves<'a>(&'a self, leaves: &mut Vec<&'a str>) {
+        if self.is_leaf() {
+            leaves.push(self.path());
+        }
+        for child in &self.children {
+            child.collect_leaves(leaves);
+        }
+    }
+}
+
+/// Suffix of the names of the templates in a directory.
+pub const TEMPLATE_SUFFIX: &str = ".hbs";
+/// Directory holding the partials, next to a template or in a directory of templates.
+const PARTIALS_DIR: &str = "partials";
+
+/// Hold information about a Handlebars template, or a directory of templates.
+#[derive(Debug, Clone)]
 pub struct Document {
     path: PathBuf,
-    variables: Vec<String>,
+    files: Option<Vec<PathBuf>>,
+    partials: BTreeMap<String, PathBuf>,
+    scripts: BTreeMap<String, PathBuf>,
+    variables: Vec<VariableNode>,
+    metadata: Metadata,
+    constraints: BTreeMap<String, Constraints>,
+    strict: bool,
+}
+
+/// Output of a file of a directory of templates.
+enum Output {
+    Rendered(String),
+    Copied(PathBuf),
 }
 
 impl Document {
     /// Create a new document for Handlebars template at `path`.
-    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+    ///
+    /// The metadata of the variables is read from the front matter of the
+    /// template or from its sidecar file.
+    ///
+    /// If `path` is a directory, the variables of all the `.hbs` files in it
+    /// are merged, including those in the names of its files.
+    ///
+    /// The partials are the `.hbs` files in the `partials` directory next to the
+    /// template, then in `partials_dirs`, named after their path without suffix.
+    ///
+    /// The `.rhai` files in the `helpers` directory next to the template are
+    /// registered as script helpers, named after the file without suffix. A
+    /// script can not replace a built-in helper.
+    pub fn open<P: AsRef<Path>>(path: P, partials_dirs: &[PathBuf]) -> Result<Self, Error> {
         let path = path.as_ref();
-        let variables = Self::collect_variables(path)?;
-        Ok(Self {
+        let sibling = |name: &str| {
+            if path.is_dir() {
+                path.join(name)
+            } else {
+                path.with_file_name(name)
+            }
+        };
+        let local_dir = sibling(PARTIALS_DIR);
+        let mut partials = BTreeMap::new();
+        for dir in std::iter::once(&local_dir).chain(partials_dirs) {
+            if dir.is_dir() {
+                for file in list_files(dir)? {
+                    let name = relative_name(dir, &file)?;
+                    if let Some(name) = name.strip_suffix(TEMPLATE_SUFFIX) {
+                        partials.entry(name.to_string()).or_insert(dir.join(file));
+                    }
+                }
+            }
+        }
+        let mut scripts = BTreeMap::new();
+        let scripts_dir = sibling(SCRIPTS_DIR);
+        if scripts_dir.is_dir() {
+            for entry in fs::read_dir(&scripts_dir)? {
+                let file = entry?.path();
+                let name = file.file_name().and_then(|n| n.to_str());
+                if let Some(name) = name.and_then(|n| n.strip_suffix(SCRIPT_SUFFIX)) {
+                    if HELPERS.contains(&name) || BUILTIN_HELPERS.contains(&name) {
+                        return Err(Error::Script(
+                            name.to_string(),
+                            "it has the name of a built-in helper".to_string(),
+                        ));
+                    }
+                    scripts.insert(name.to_string(), file.clone());
+                }
+            }
+        }
+        let mut collector = Collector::default();
+        collector.helpers.extend(scripts.keys().cloned());
+        // A partial which does not parse only matters if it is used.
+        let mut invalid_partials = BTreeMap::new();
+        for (name, file) in &partials {
+            match Template::compile_with_name(fs::read_to_string(file)?, name.clone()) {
+                Ok(template) => {
+                    collector.partials.insert(name.clone(), template);
+                }
+                Err(error) => {
+                    invalid_partials.insert(name.clone(), error);
+                }
+            }
+        }
+        partials.retain(|name, _| !invalid_partials.contains_key(name));
+        let mut metadata = Metadata::default();
+        let files = if path.is_dir() {
+            let files = list_files(path)?;
+            for file in &files {
+                let name = relative_name(path, file)?;
+                collector.collect(&name, &name)?;
+                if name.ends_with(TEMPLATE_SUFFIX) {
+                    let contents = fs::read_to_string(path.join(file))?;
+                    metadata.merge(Metadata::load(&path.join(file), &contents)?);
+                    collector.collect(&name, &contents)?;
+                }
+            }
+            Some(files)
+        } else {
+            let name = path
+                .file_name()
+                .and_then(|f| f.to_str())
+                .ok_or_else(|| Error::InvalidTemplate(path.into()))?;
+            let contents = fs::read_to_string(path)?;
+            metadata = Metadata::load(path, &contents)?;
+            collector.collect(name, &contents)?;
+            None
+        };
+        if let Some(name) = collector.missing_partials.first() {
+            return Err(match invalid_partials.remove(name) {
+                Some(error) => error.into(),
+                None => Error::MissingPartial(name.clone()),
+            });
+        }
+        for (name, error) in &invalid_partials {
+            warn!("Ignoring invalid partial {}: {}", name, error);
+        }
+        for name in collector.helper_clashes() {
+            warn!(
+                "Variable {} has the name of a helper: print it with {{{{this.{}}}}}",
+                name, name
+            );
+        }
+        let mut variables = collector.tree();
+        VariableNode::apply_kinds(&mut variables, &metadata);
+        let constraints = metadata
+            .variables
+            .iter()
+            .map(|(path, meta)| Ok((path.clone(), Constraints::from_meta(meta)?)))
+            .collect::<Result<_, Error>>()?;
+        let document = Self {
             path: path.into(),
+            files,
+            partials,
+            scripts,
             variables,
-        })
-    }
-    /// Collect the variables in the underlying template as a list.
-    fn collect_variables(path: &Path) -> Result<Vec<String>, Error> {
-        let name = path
-            .file_name()
-            .and_then(|f| f.to_str())
-            .ok_or_else(|| Error::InvalidTemplate(path.into()))?;
-        let mut handlebars = Handlebars::new();
-        handlebars.register_template_file(name, path)?;
-        let template = handlebars
-            .get_template(name)
-            .expect("Template should have been registered");
-        let variables: BTreeSet<String> = template
-            .elements
-            .iter()
-            .filter_map(|e| {
-                if let TemplateElement::Expression(h) = e {
-                    Some(h)
-                } else {
-                    None
-                }
-            })
-            .filter_map(|h| {
-                if let Parameter::Path(JsonPath::Relative(p)) = &h.name {
-                    Some(p)
-                } else {
-                    None
-                }
-            })
-            .flat_map(|p| &p.0)
-            .filter_map(|s| {
-                if let PathSeg::Named(n) = s {
-                    Some(n)
-                } else {
-                    None
-                }
-            })
-            .cloned()
-            .collect();
-        Ok(Vec::from_iter(variables))
+            metadata,
+            constraints,
+            strict: false,
+        };
+        // Check that the partials and the script helpers can be registered.
+        document.registry()?;
+        Ok(document)
+    }
+    /// Return `true` if the document is a directory of templates.
+    pub fn is_directory(&self) -> bool {
+        self.files.is_some()
     }
     /// Return the path of the document.
     pub fn path(&self) -> &Path {
         self.path.as_path()
     }
+    /// Return the default values of the variables, set in the metadata.
+    pub fn defaults(&self) -> Value {
+        build_data(
+            self.metadata
+                .variables
+                .iter()
+                .filter_map(|(path, meta)| meta.default.clone().map(|value| (path, value))),
+        )
+    }
+    /// Enable or disable strict rendering, failing if a variable is missing from the data.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+    /// Return `true` if rendering fails when a variable is missing from the data.
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
     /// Render the underlying template to a file at `path`, using `data`.
-    pub fn render_to_file<P: AsRef<Path>>(
-        &self,
-        path: P,
-        data: &HashMap<String, String>,
-    ) -> Result<(), Error> {
+    ///
+    /// The file is left untouched if rendering fails.
+    pub fn render_to_file<P: AsRef<Path>>(&self, path: P, data: &Value) -> Result<(), Error> {
+        let output = self.render_to_string(data)?;
+        fs::write(path, output)?;
+        Ok(())
+    }
+    /// Render the templates of a directory into the directory at `path`, using `data`.
+    ///
+    /// The names of the files are rendered too, without the `.hbs` suffix for
+    /// the templates. The other files are copied verbatim. Nothing is written
+    /// if rendering fails.
+    pub fn render_to_dir<P: AsRef<Path>>(&self, path: P, data: &Value) -> Result<(), Error> {
+        for (name, output) in self.render_files(data)? {
+            let target = path.as_ref().join(name);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            match output {
+                Output::Rendered(text) => fs::write(target, text)?,
+                Output::Copied(source) => {
+                    fs::copy(source, target)?;
+                }
+            }
+        }
+        Ok(())
+    }
+    /// Render the underlying template to a string, using `data`.
+    ///
+    /// For a directory, the rendered templates follow each other, after a
+    /// header with their name.
+    pub fn render_to_string(&self, data: &Value) -> Result<String, Error> {
+        if self.is_directory() {
+            let mut output = String::new();
+            for (name, file) in self.render_files(data)? {
+                if let Output::Rendered(text) = file {
+                    output.push_str(&format!("==> {} <==\n{}\n", name, text));
+                }
+            }
+            return Ok(output);
+        }
+        let contents = fs::read_to_string(&self.path)?;
+        let (_, body, offset) = metadata::split_front_matter(&contents);
+        let output = self
+            .registry()?
+            .render_template(body, data)
+            .map_err(|e| shift_error(e, None, offset))?;
+        Ok(output)
+    }
+    /// Render the underlying template to `writer`, using `data`.
+    pub fn render_to_writer<W: Write>(&self, mut writer: W, data: &Value) -> Result<(), Error> {
+        if self.is_directory() {
+            writer.write_all(self.render_to_string(data)?.as_bytes())?;
+            return Ok(());
+        }
         let contents = fs::read_to_string(&self.path)?;
-        let output = fs::File::create(path)?;
-        let handlebars = Handlebars::new();
-        handlebars.render_template_to_write(&contents, data, output)?;
+        let (_, body, offset) = metadata::split_front_matter(&contents);
+        self.registry()?
+            .render_template_to_write(body, data, writer)
+            .map_err(|e| shift_error(e, None, offset))?;
         Ok(())
     }
-    /// Return the list of variables in the underlying template.
+    /// Render the names and contents of the files of a directory, using `data`.
+    ///
+    /// The names are not escaped, and must stay inside the directory.
+    fn render_files(&self, data: &Value) -> Result<Vec<(String, Output)>, Error> {
+        let registry = self.registry()?;
+        let mut names = registry.clone();
+        names.register_escape_fn(handlebars::no_escape);
+        let mut outputs = Vec::new();
+        for file in self.files.iter().flatten() {
+            let name = relative_name(&self.path, file)?;
+            let rendered = names
+                .render_template(&name, data)
+                .map_err(|e| shift_error(e, Some(&name), 0))?;
+            check_file_name(rendered.strip_suffix(TEMPLATE_SUFFIX).unwrap_or(&rendered))?;
+            let source = self.path.join(file);
+            let output = match rendered.strip_suffix(TEMPLATE_SUFFIX) {
+                Some(rendered) => {
+                    let contents = fs::read_to_string(&source)?;
+                    let (_, body, offset) = metadata::split_front_matter(&contents);
+                    let text = registry
+                        .render_template(body, data)
+                        .map_err(|e| shift_error(e, Some(&name), offset))?;
+                    (rendered.to_string(), Output::Rendered(text))
+                }
+                None => (rendered, Output::Copied(source)),
+            };
+            outputs.push(output);
+        }
+        Ok(outputs)
+    }
+    /// Create the registry to render the underlying template, with its partials.
+    fn registry(&self) -> Result<Handlebars<'static>, Error> {
+        let mut handlebars = Handlebars::new();
+        handlebars.set_strict_mode(self.strict);
+        helpers::register(&mut handlebars);
+        for (name, path) in &self.partials {
+            handlebars.register_partial(name, fs::read_to_string(path)?)?;
+        }
+        handlebars.set_engine(helpers::script_engine());
+        for (name, path) in &self.scripts {
+            handlebars
+                .register_script_helper(name, &fs::read_to_string(path)?)
+                .map_err(|e| Error::Script(name.clone(), e.to_string()))?;
+        }
+        Ok(handlebars)
+    }
+    /// Return the full paths of the variables holding a value in the underlying template.
     pub fn variables(&self) -> impl Iterator<Item = &str> {
-        self.variables.iter().map(|s| s.as_str())
+        let mut leaves = Vec::new();
+        for node in &self.variables {
+            node.collect_leaves(&mut leaves);
+        }
+        leaves.into_iter()
+    }
+    /// Return the tree of variables in the underlying template.
+    pub fn tree(&self) -> &[VariableNode] {
+        self.variables.as_slice()
+    }
+    /// Return the information declared about the variable at dotted `path`, if any.
+    pub fn metadata(&self, path: &str) -> Option<&VariableMeta> {
+        self.metadata.get(path)
+    }
+    /// Return the constraints on the value of the variable at dotted `path`, if any.
+    pub fn constraints(&self, path: &str) -> Option<&Constraints> {
+        self.constraints.get(path)
+    }
+    /// Check `data` against the constraints declared in the metadata.
+    pub fn validate(&self, data: &Value) -> Result<(), Error> {
+        let violations = validation::validate_data(&self.constraints, data);
+        if violations.is_empty() {
+            return Ok(());
+        }
+        let lines: Vec<String> = violations
+            .iter()
+            .map(|(path, violation)| format!("{}: {}", path, violation))
+            .collect();
+        Err(Error::Validation(lines.join("\n")))
+    }
+}
+
+/// Move the position of a render error past the `offset` lines of the front matter.
+///
+/// The error is also attributed to the template `name`, if any.
+fn shift_error(
+    mut error: handlebars::RenderError,
+    name: Option<&str>,
+    offset: usize,
+) -> handlebars::RenderError {
+    error.line_no = error.line_no.map(|line| line + offset);
+    if let Some(name) = name {
+        error.template_name = Some(name.to_string());
+    }
+    error
+}
+
+/// List the files in the directory at `root`, recursively, relative to it.
+///
+/// The sidecar files holding the metadata and the profiles of the templates,
+/// the partials and the script helpers are skipped. A directory linked more than
+/// once is only listed the first time.
+fn list_files(root: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut files = Vec::new();
+    let mut directories = vec![PathBuf::new()];
+    let mut visited = BTreeSet::new();
+    while let Some(directory) = directories.pop() {
+        if !visited.insert(fs::canonicalize(root.join(&directory))?) {
+            continue;
+        }
+        for entry in fs::read_dir(root.join(&directory))? {
+            let path = directory.join(entry?.file_name());
+            if path == Path::new(PARTIALS_DIR) || path == Path::new(SCRIPTS_DIR) {
+                continue;
+            }
+            if root.join(&path).is_dir() {
+                let profiles = format!("{}{}", TEMPLATE_SUFFIX, PROFILES_SUFFIX);
+                if !path.to_string_lossy().ends_with(&profiles) {
+                    directories.push(path);
+                }
+            } else {
+                files.push(path);
+            }
+        }
+    }
+    let sidecars: BTreeSet<PathBuf> = files
+        .iter()
+        .filter(|f| f.to_string_lossy().ends_with(TEMPLATE_SUFFIX))
+        .map(|f| metadata::sidecar_path(f))
+        .collect();
+    files.retain(|f| !sidecars.contains(f));
+    files.sort();
+    Ok(files)
+}
+
+/// Return `name` as a path relative to a directory, failing if it is empty or
+/// leaves the directory.
+pub fn check_file_name(name: &str) -> Result<PathBuf, Error> {
+    let path = PathBuf::from(name);
+    let valid = path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    if !valid || path.as_os_str().is_empty() {
+        return Err(Error::InvalidFileName(name.to_string()));
+    }
+    Ok(path)
+}
+
+/// Return the path of `file` in a directory at `root`, with `/` as separator.
+fn relative_name(root: &Path, file: &Path) -> Result<String, Error> {
+    file.components()
+        .map(|c| c.as_os_str().to_str())
+        .collect::<Option<Vec<_>>>()
+        .map(|parts| parts.join("/"))
+        .ok_or_else(|| Error::InvalidTemplate(root.join(file)))
+}
+
+/// Build the data to render a template from pairs of dotted paths and values.
+pub fn build_data<I, K, V>(values: I) -> Value
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: AsRef<str>,
+    V: Into<Value>,
+{
+    let mut data = Value::Object(Map::new());
+    for (path, value) in values {
+        insert_value(&mut data, path.as_ref(), value.into());
+    }
+    data
+}
+
+/// Insert `value` in `data` at dotted `path`, creating intermediate objects as needed.
+pub fn insert_value(data: &mut Value, path: &str, value: Value) {
+    let mut current = data;
+    let mut segments = path.split('.').peekable();
+    while let Some(segment) = segments.next() {
+        if !current.is_object() {
+            *current = Value::Object(Map::new());
+        }
+        let object = current.as_object_mut().expect("Value should be an object");
+        if segments.peek().is_none() {
+            object.insert(segment.to_string(), value);
+            return;
+        }
+        current = object
+            .entry(segment.to_string())
+            .or_insert_with(|| Value::Object(Map::new()));
+    }
+}
+
+/// Lookup scope opened by a block helper changing the context.
+#[derive(Debug)]
+struct Scope {
+    path: Vec<String>,
+    alias: Option<String>,
+    locals: BTreeSet<String>,
+}
+
+/// Walk a template to find the variables looked up when rendering it.
+#[derive(Debug, Default)]
+struct Collector {
+    variables: BTreeSet<Vec<String>>,
+    lists: BTreeSet<Vec<String>>,
+    hints: BTreeMap<Vec<String>, VariableKind>,
+    printed: BTreeSet<Vec<String>>,
+    choices: BTreeMap<Vec<String>, Vec<Value>>,
+    scopes: Vec<Scope>,
+    partials: BTreeMap<String, Template>,
+    inline_partials: BTreeSet<String>,
+    missing_partials: Vec<String>,
+    visited_partials: Vec<String>,
+    helpers: BTreeSet<String>,
+}
+
+impl Collector {
+    /// Walk the template `name`, whose `contents` may start with a front matter.
+    fn collect(&mut self, name: &str, contents: &str) -> Result<(), Error> {
+        let (_, body, offset) = metadata::split_front_matter(contents);
+        let mut handlebars = Handlebars::new();
+        handlebars
+            .register_template_string(name, body)
+            .map_err(|e| match e.pos() {
+                Some((line, column)) => e.at(contents, line + offset, column),
+                None => e,
+            })?;
+        let template = handlebars
+            .get_template(name)
+            .expect("Template should have been registered");
+        self.visit_template(template);
+        Ok(())
+    }
+
+    /// Return the tree of the variables found so far.
+    fn tree(&self) -> Vec<VariableNode> {
+        let paths = Vec::from_iter(self.variables.iter().cloned());
+        VariableNode::build_tree(&[], &paths, self)
+    }
+
+    /// Return the top-level variables named like a helper, which `{{name}}` does not print.
+    fn helper_clashes(&self) -> BTreeSet<&str> {
+        self.variables
+            .iter()
+            .filter_map(|path| path.first())
+            .map(String::as_str)
+            .filter(|name| HELPERS.contains(name) || self.helpers.contains(*name))
+            .collect()
+    }
+
+    fn visit_template(&mut self, template: &Template) {
+        for element in &template.elements {
+            self.visit_element(element);
+        }
+    }
+
+    fn visit_element(&mut self, element: &TemplateElement) {
+        match element {
+            TemplateElement::Expression(h) | TemplateElement::HtmlExpression(h) => {
+                // A name without parameters is a lookup, unless it is a known helper,
+                // otherwise it is a helper call.
+                let is_helper = h
+                    .name
+                    .as_name()
+                    .is_some_and(|n| HELPERS.contains(&n) || self.helpers.contains(n));
+                if h.params.is_empty() && h.hash.is_empty() && !is_helper {
+                    if let Parameter::Path(JsonPath::Relative((segs, raw))) = &h.name {
+                        let path = self.resolve(segs, raw);
+                        self.printed.extend(path);
+                    }
+                    self.visit_parameter(&h.name);
+                }
+                self.visit_helper(h);
+            }
+            TemplateElement::HelperBlock(h) => self.visit_helper(h),
+            TemplateElement::DecoratorExpression(d) | TemplateElement::DecoratorBlock(d) => {
+                if let (Parameter::Name(name), Some(Parameter::Literal(Value::String(partial)))) =
+                    (&d.name, d.params.first())
+                {
+                    if name == "inline" {
+                        self.inline_partials.insert(partial.clone());
+                    }
+                }
+                self.visit_decorator(d)
+            }
+            TemplateElement::PartialExpression(d) => {
+                self.visit_decorator(d);
+                self.visit_partial(d, false);
+            }
+            TemplateElement::PartialBlock(d) => {
+                self.visit_decorator(d);
+                self.visit_partial(d, true);
+            }
+            _ => {}
+        }
+    }
+
+    /// Walk the partial included by `partial`, in the context it is given.
+    ///
+    /// A missing partial is recorded, unless the block has fallback content.
+    fn visit_partial(&mut self, partial: &DecoratorTemplate, has_fallback: bool) {
+        let Parameter::Name(name) = &partial.name else {
+            return;
+        };
+        if name.starts_with('@')
+            || self.inline_partials.contains(name)
+            || self.visited_partials.contains(name)
+        {
+            return;
+        }
+        let Some(template) = self.partials.get(name).cloned() else {
+            if !has_fallback && !self.missing_partials.contains(name) {
+                self.missing_partials.push(name.clone());
+            }
+            return;
+        };
+        let context = match partial.params.first() {
+            Some(Parameter::Path(JsonPath::Relative((segs, raw)))) => self.resolve(segs, raw),
+            _ => None,
+        };
+        let path = context
+            .or_else(|| self.scopes.last().map(|s| s.path.clone()))
+            .unwrap_or_default();
+        self.scopes.push(Scope {
+            path,
+            alias: None,
+            locals: partial.hash.keys().cloned().collect(),
+        });
+        self.visited_partials.push(name.clone());
+        self.visit_template(&template);
+        self.visited_partials.pop();
+        self.scopes.pop();
+    }
+
+    fn visit_helper(&mut self, helper: &HelperTemplate) {
+        if let Parameter::Subexpression(_) = &helper.name {
+            self.visit_parameter(&helper.name);
+        }
+        self.infer_kinds(helper);
+        for param in &helper.params {
+            self.visit_parameter(param);
+        }
+        for param in helper.hash.values() {
+            self.visit_parameter(param);
+        }
+        if let Some(template) = &helper.template {
+            let scope = self.block_scope(helper);
+            let scoped = scope.is_some();
+            self.scopes.extend(scope);
+            self.visit_template(template);
+            if scoped {
+                self.scopes.pop();
+            }
+        }
+        if let Some(template) = &helper.inverse {
+            self.visit_template(template);
+        }
+    }
+
+    fn visit_decorator(&mut self, decorator: &DecoratorTemplate) {
+        if let Parameter::Subexpression(_) = &decorator.name {
+            self.visit_parameter(&decorator.name);
+        }
+        for param in &decorator.params {
+            self.visit_parameter(param);
+        }
+        for param in decorator.hash.values() {
+            self.visit_parameter(param);
+        }
+        if let Some(template) = &decorator.template {
+            self.visit_template(template);
+        }
+    }
+
+    fn visit_parameter(&mut self, param: &Parameter) {
+        match param {
+            Parameter::Path(JsonPath::Relative((segs, raw))) => {
+                if let Some(path) = self.resolve(segs, raw) {
+                    self.variables.insert(path);
+                }
+            }
+            Parameter::Subexpression(subexpr) => self.visit_element(&subexpr.element),
+            _ => {}
+        }
+    }
+
+    /// Infer the kinds of the variables passed as parameters to a helper.
+    fn infer_kinds(&mut self, helper: &HelperTemplate) {
+        let Parameter::Name(name) = &helper.name else {
+            return;
+        };
+        let literal = helper.params.iter().find_map(|p| match p {
+            Parameter::Literal(value) => Some(value),
+            _ => None,
+        });
+        let kind = match name.as_str() {
+            "if" | "unless" if helper.block => VariableKind::Boolean,
+            "and" | "or" | "not" => VariableKind::Boolean,
+            "gt" | "gte" | "lt" | "lte" | "pluralize" => VariableKind::Number,
+            "eq" | "ne" => match literal {
+                Some(Value::Bool(_)) => VariableKind::Boolean,
+                Some(Value::Number(_)) => VariableKind::Number,
+                Some(Value::String(_)) => VariableKind::Choice,
+                _ => return,
+            },
+            _ => return,
+        };
+        for param in &helper.params {
+            if let Parameter::Path(JsonPath::Relative((segs, raw))) = param {
+                if let Some(path) = self.resolve(segs, raw) {
+                    if let Some(choice @ Value::String(_)) = literal {
+                        let choices = self.choices.entry(path.clone()).or_default();
+                        if !choices.contains(choice) {
+                            choices.push(choice.clone());
+                        }
+                    }
+                    self.hints.insert(path, kind);
+                }
+            }
+        }
+    }
+
+    /// Return the scope opened by the body of a `with` or `each` block.
+    fn block_scope(&mut self, helper: &HelperTemplate) -> Option<Scope> {
+        match (&helper.name, helper.params.first()) {
+            (Parameter::Name(name), Some(Parameter::Path(JsonPath::Relative((segs, raw)))))
+                if name == "with" || name == "each" =>
+            {
+                let path = self.resolve(segs, raw)?;
+                if name == "each" {
+                    self.lists.insert(path.clone());
+                }
+                // The second block parameter is the index or the key of the item.
+                let (alias, locals) = match &helper.block_param {
+                    Some(BlockParam::Single(Parameter::Name(alias))) => {
+                        (Some(alias.clone()), BTreeSet::new())
+                    }
+                    Some(BlockParam::Pair((Parameter::Name(alias), Parameter::Name(index)))) => {
+                        (Some(alias.clone()), BTreeSet::from([index.clone()]))
+                    }
+                    _ => (None, BTreeSet::new()),
+                };
+                Some(Scope {
+                    path,
+                    alias,
+                    locals,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Resolve a path relative to the current scope into a full path.
+    fn resolve(&self, segs: &[PathSeg], raw: &str) -> Option<Vec<String>> {
+        let mut names = Vec::new();
+        let mut ups = 0;
+        for seg in segs {
+            match seg {
+                PathSeg::Named(name) => names.push(name.clone()),
+                _ => ups += 1,
+            }
+        }
+        let shadowed = self
+            .scopes
+            .last()
+            .is_some_and(|s| names.first().is_some_and(|n| s.locals.contains(n)));
+        let mut path = if raw.starts_with("@root") {
+            Vec::new()
+        } else if ups == 0 && shadowed {
+            return None;
+        } else if let Some(scope) = self
+            .scopes
+            .iter()
+            .rev()
+            .find(|s| ups == 0 && s.alias.is_some() && s.alias.as_ref() == names.first())
+        {
+            names.remove(0);
+            scope.path.clone()
+        } else {
+            self.scopes
+                .len()
+                .checked_sub(ups + 1)
+                .map(|index| self.scopes[index].path.clone())
+                .unwrap_or_default()
+        };
+        path.extend(names);
+        if path.is_empty() {
+            None
+        } else {
+            Some(path)
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use serde_json::json;
     use std::fs::File;
     use std::io::Write;
 
@@ -118,6 +1022,65 @@ mod tests {
   lastname: {{lastname}}
 """#;
     const VARIABLES_VALID: &[&str] = &["firstname", "lastname"];
+    const TEMPLATE_BLOCKS: &str = r#"""
+{{#if draft}}DRAFT {{status}}{{else}}{{version}}{{/if}}
+{{#unless (eq kind "internal")}}{{audience}}{{/unless}}
+{{#with owner}}{{email}}{{/with}}
+{{#each items}}- {{label}}{{/each}}
+{{format date pattern=fmt}}
+"""#;
+    const VARIABLES_BLOCKS: &[&str] = &[
+        "audience",
+        "date",
+        "draft",
+        "fmt",
+        "items.label",
+        "kind",
+        "owner.email",
+        "status",
+        "version",
+    ];
+    const TEMPLATE_NESTED: &str = r#"""
+{{author.name}} <{{author.email}}>
+{{#with project as |p|}}{{p.name}} {{../author.name}} {{@root.year}}{{/with}}
+"""#;
+    const VARIABLES_NESTED: &[&str] = &["author.email", "author.name", "project.name", "year"];
+    const TEMPLATE_KINDS: &str = r#"""
+{{#if draft}}DRAFT{{/if}}
+{{#if (gt count 10)}}many{{else if (eq level 2)}}{{label}}{{/if}}
+{{#unless (not visible)}}{{owner.name}}{{/unless}}
+{{#if (eq env "prod")}}P{{else if (eq env "dev")}}D{{else if (ne env "prod")}}?{{/if}}
+{{#if title}}Title: {{title}}{{/if}}
+"""#;
+    const KINDS: &[(&str, VariableKind)] = &[
+        ("count", VariableKind::Number),
+        ("draft", VariableKind::Boolean),
+        ("env", VariableKind::Choice),
+        ("label", VariableKind::String),
+        ("level", VariableKind::Number),
+        ("owner", VariableKind::Object),
+        ("title", VariableKind::String),
+        ("visible", VariableKind::Boolean),
+    ];
+    const TEMPLATE_LISTS: &str = r#"""
+{{#each contributors}}
+- {{name}} ({{@index}}) for {{../title}}:{{#each emails}} {{this}}{{/each}}
+{{/each}}
+{{#each tags as |tag|}}{{tag.label}}{{/each}}
+{{#each steps as |step idx|}}{{idx}}. {{step.title}}{{/each}}
+"""#;
+    const TEMPLATE_HELPERS: &str = r#"""
+{{now "%Y"}} {{now}} {{upper (default title "Untitled")}} {{date due "%d/%m"}}
+{{count}} {{pluralize count "item"}} {{join (lower tags) ", "}}
+"""#;
+    const VARIABLES_HELPERS: &[&str] = &["count", "due", "tags", "title"];
+    const VARIABLES_LISTS: &[&str] = &[
+        "contributors.emails",
+        "contributors.name",
+        "steps.title",
+        "tags.label",
+        "title",
+    ];
 
     #[test]
     fn get_variables_valid() {
@@ -125,10 +1088,415 @@ mod tests {
         let path = dir.path().join("template-valid.yml.hbs");
         let mut input = File::create(&path).unwrap();
         writeln!(input, "{}", TEMPLATE_VALID).unwrap();
-        let res = Document::open(path);
+        let res = Document::open(path, &[]);
         assert!(res.is_ok());
         let doc = res.unwrap();
         let variables: Vec<&str> = doc.variables().collect();
         assert_eq!(&variables, VARIABLES_VALID);
     }
+
+    #[test]
+    fn get_variables_blocks() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("template-blocks.txt.hbs");
+        let mut input = File::create(&path).unwrap();
+        writeln!(input, "{}", TEMPLATE_BLOCKS).unwrap();
+        let res = Document::open(path, &[]);
+        assert!(res.is_ok());
+        let doc = res.unwrap();
+        let variables: Vec<&str> = doc.variables().collect();
+        assert_eq!(&variables, VARIABLES_BLOCKS);
+    }
+
+    #[test]
+    fn get_variables_nested() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("template-nested.txt.hbs");
+        let mut input = File::create(&path).unwrap();
+        writeln!(input, "{}", TEMPLATE_NESTED).unwrap();
+        let doc = Document::open(path, &[]).unwrap();
+        let variables: Vec<&str> = doc.variables().collect();
+        assert_eq!(&variables, VARIABLES_NESTED);
+        let author = &doc.tree()[0];
+        assert_eq!(author.name(), "author");
+        assert_eq!(author.kind(), VariableKind::Object);
+        let names: Vec<&str> = author.children().iter().map(|c| c.name()).collect();
+        assert_eq!(&names, &["email", "name"]);
+    }
+
+    #[test]
+    fn get_variables_helpers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("template-helpers.txt.hbs");
+        let mut input = File::create(&path).unwrap();
+        writeln!(input, "{}", TEMPLATE_HELPERS).unwrap();
+        let doc = Document::open(path, &[]).unwrap();
+        let variables: Vec<&str> = doc.variables().collect();
+        assert_eq!(&variables, VARIABLES_HELPERS);
+        assert_eq!(doc.tree()[0].kind(), VariableKind::Number);
+        let data = json!({"count": 3, "due": "2024-05-01", "tags": "A"});
+        let output = doc.render_to_string(&data).unwrap();
+        assert!(
+            output.contains(" UNTITLED 01/05\n3 items a\n"),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn get_helper_clashes() {
+        let mut collector = Collector::default();
+        collector
+            .collect(
+                "clashes",
+                "{{date}} {{this.date}} {{upper default}} {{now.year}}",
+            )
+            .unwrap();
+        let clashes = Vec::from_iter(collector.helper_clashes());
+        assert_eq!(clashes, ["date", "default", "now"]);
+    }
+
+    #[test]
+    fn get_variables_lists() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("template-lists.txt.hbs");
+        let mut input = File::create(&path).unwrap();
+        writeln!(input, "{}", TEMPLATE_LISTS).unwrap();
+        let doc = Document::open(path, &[]).unwrap();
+        let variables: Vec<&str> = doc.variables().collect();
+        assert_eq!(&variables, VARIABLES_LISTS);
+        let kinds: Vec<VariableKind> = doc.tree().iter().map(|n| n.kind()).collect();
+        assert_eq!(
+            &kinds,
+            &[
+                VariableKind::List,
+                VariableKind::List,
+                VariableKind::List,
+                VariableKind::String
+            ]
+        );
+        let emails = &doc.tree()[0].children()[0];
+        assert_eq!(emails.path(), "contributors.emails");
+        assert_eq!(emails.kind(), VariableKind::List);
+    }
+
+    #[test]
+    fn get_variables_kinds() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("template-kinds.txt.hbs");
+        let mut input = File::create(&path).unwrap();
+        writeln!(input, "{}", TEMPLATE_KINDS).unwrap();
+        let doc = Document::open(path, &[]).unwrap();
+        let kinds: Vec<(&str, VariableKind)> =
+            doc.tree().iter().map(|n| (n.path(), n.kind())).collect();
+        assert_eq!(&kinds, KINDS);
+        assert_eq!(doc.tree()[2].choices(), ["prod", "dev"]);
+    }
+
+    #[test]
+    fn parse_values() {
+        assert_eq!(VariableKind::Boolean.parse("true"), Value::Bool(true));
+        assert_eq!(VariableKind::Boolean.parse(""), Value::Bool(false));
+        assert_eq!(VariableKind::Number.parse("42"), Value::from(42));
+        assert_eq!(VariableKind::Number.parse("4.5"), Value::from(4.5));
+        assert_eq!(VariableKind::Number.parse("n/a"), Value::Null);
+        assert_eq!(VariableKind::String.parse("42"), Value::from("42"));
+        assert_eq!(VariableKind::Number.convert("42"), Some(Value::from(42)));
+        assert_eq!(VariableKind::Number.convert(""), Some(Value::Null));
+        assert_eq!(VariableKind::Number.convert("n/a"), None);
+        assert_eq!(VariableKind::Boolean.convert("yes"), None);
+    }
+
+    #[test]
+    fn convert_choices() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("template-choices.txt.hbs");
+        fs::write(
+            &path,
+            "---\nvariables:\n  level:\n    enum: [1, 2.5, true]\n---\n\
+             {{#if (eq level 1)}}first{{/if}}",
+        )
+        .unwrap();
+        let doc = Document::open(&path, &[]).unwrap();
+        let level = &doc.tree()[0];
+        assert_eq!(level.kind(), VariableKind::Choice);
+        assert_eq!(level.convert("1"), Some(json!(1)));
+        assert_eq!(level.convert("true"), Some(json!(true)));
+        assert_eq!(level.convert("3"), Some(json!("3")));
+        let data = json!({"level": level.convert("1").unwrap()});
+        assert_eq!(doc.render_to_string(&data).unwrap(), "first");
+    }
+
+    #[test]
+    fn report_error_position() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("template-invalid.txt.hbs");
+        let mut input = File::create(&path).unwrap();
+        write!(input, "Hello\n{{{{#if draft}}}}DRAFT").unwrap();
+        let error = Document::open(&path, &[]).unwrap_err();
+        assert!(matches!(error, Error::Template(_)));
+        assert!(error.position().is_some());
+        let path = dir.path().join("template-render.txt.hbs");
+        let mut input = File::create(&path).unwrap();
+        write!(input, "Hello\n  {{{{missing name}}}}").unwrap();
+        let doc = Document::open(&path, &[]).unwrap();
+        let error = doc.render_to_string(&Value::Null).unwrap_err();
+        assert!(matches!(error, Error::Render(_)));
+        assert_eq!(error.position(), Some((2, 3)));
+        assert!(error.summary().contains("missing"));
+    }
+
+    #[test]
+    fn render_strict() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("template-strict.txt.hbs");
+        let mut input = File::create(&path).unwrap();
+        write!(input, "{{{{#if draft}}}}DRAFT {{{{/if}}}}{{{{title}}}}").unwrap();
+        let mut doc = Document::open(&path, &[]).unwrap();
+        let data = json!({"draft": false});
+        assert_eq!(doc.render_to_string(&data).unwrap(), "");
+        doc.set_strict(true);
+        let error = doc.render_to_string(&data).unwrap_err();
+        assert!(matches!(error, Error::Render(_)));
+        let output = dir.path().join("output.txt");
+        fs::write(&output, "previous").unwrap();
+        assert!(doc.render_to_file(&output, &data).is_err());
+        assert_eq!(fs::read_to_string(&output).unwrap(), "previous");
+        let data = json!({"draft": true, "title": "Report"});
+        assert_eq!(doc.render_to_string(&data).unwrap(), "DRAFT Report");
+    }
+
+    #[test]
+    fn render_front_matter() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("template-front-matter.txt.hbs");
+        let mut input = File::create(&path).unwrap();
+        write!(
+            input,
+            "---\nvariables:\n  count:\n    type: number\n    label: Count\n---\n\
+             {{{{count}}}} item(s)\n{{{{unknown name}}}}"
+        )
+        .unwrap();
+        let doc = Document::open(&path, &[]).unwrap();
+        assert_eq!(doc.tree()[0].kind(), VariableKind::Number);
+        assert!(doc.tree()[0].choices().is_empty());
+        let label = doc.metadata("count").and_then(|m| m.label.as_deref());
+        assert_eq!(label, Some("Count"));
+        let error = doc.render_to_string(&json!({"count": 3})).unwrap_err();
+        assert_eq!(error.position(), Some((8, 1)));
+        let path = dir.path().join("template-invalid-front-matter.txt.hbs");
+        let mut input = File::create(&path).unwrap();
+        write!(input, "---\nvariables: {{}}\n---\n\n{{{{#if draft}}}}").unwrap();
+        let error = Document::open(&path, &[]).unwrap_err();
+        assert_eq!(error.position().map(|(line, _)| line), Some(5));
+        let path = dir.path().join("template-body.txt.hbs");
+        let mut input = File::create(&path).unwrap();
+        write!(input, "---\nvariables: {{}}\n---\n{{{{count}}}} item(s)").unwrap();
+        let doc = Document::open(&path, &[]).unwrap();
+        let output = doc.render_to_string(&json!({"count": 3})).unwrap();
+        assert_eq!(output, "3 item(s)");
+        let path = dir.path().join("config.yml.hbs");
+        let mut input = File::create(&path).unwrap();
+        write!(input, "---\nport: {{{{port}}}}\n---\nhost: example.com\n").unwrap();
+        let doc = Document::open(&path, &[]).unwrap();
+        let variables: Vec<&str> = doc.variables().collect();
+        assert_eq!(variables, ["port"]);
+        let output = doc.render_to_string(&json!({"port": 80})).unwrap();
+        assert_eq!(output, "---\nport: 80\n---\nhost: example.com\n");
+    }
+
+    #[test]
+    fn get_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("template-defaults.txt.hbs");
+        fs::write(
+            &path,
+            "---\nvariables:\n  count:\n    default: 1\n  author.name:\n    default: Jane\n\
+             \x20 title:\n    label: Title\n---\n{{title}} {{count}} {{author.name}}",
+        )
+        .unwrap();
+        let doc = Document::open(&path, &[]).unwrap();
+        assert_eq!(
+            doc.defaults(),
+            json!({"count": 1, "author": {"name": "Jane"}})
+        );
+    }
+
+    #[test]
+    fn validate_front_matter() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("template-validate.txt.hbs");
+        let mut input = File::create(&path).unwrap();
+        write!(
+            input,
+            "---\nvariables:\n  email:\n    format: email\n---\n{{{{email}}}}"
+        )
+        .unwrap();
+        let doc = Document::open(&path, &[]).unwrap();
+        assert!(doc.constraints("email").is_some());
+        assert!(doc.validate(&json!({"email": "jane@example.com"})).is_ok());
+        let error = doc.validate(&json!({"email": "jane"})).unwrap_err();
+        assert!(matches!(error, Error::Validation(_)));
+        let path = dir.path().join("template-pattern.txt.hbs");
+        let mut input = File::create(&path).unwrap();
+        write!(
+            input,
+            "---\nvariables:\n  id:\n    pattern: '[0-9'\n---\n{{{{id}}}}"
+        )
+        .unwrap();
+        let error = Document::open(&path, &[]).unwrap_err();
+        assert!(matches!(error, Error::Pattern(_)));
+    }
+
+    #[test]
+    fn render_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("skeleton");
+        fs::create_dir_all(root.join("src/{{module}}")).unwrap();
+        fs::write(root.join("README.md.hbs"), "# {{name}}\n").unwrap();
+        fs::write(
+            root.join("README.md.hbs.yml"),
+            "variables:\n  name:\n    label: Name\n",
+        )
+        .unwrap();
+        fs::write(root.join("LICENSE"), "{{not rendered}}").unwrap();
+        fs::create_dir(root.join("README.md.hbs.profiles")).unwrap();
+        fs::write(root.join("README.md.hbs.profiles/acme.json"), "{}").unwrap();
+        fs::write(
+            root.join("src/{{module}}/{{name}}.rs.hbs"),
+            "---\nvariables:\n  version:\n    type: number\n---\n// {{name}} {{version}}\n",
+        )
+        .unwrap();
+        let doc = Document::open(&root, &[]).unwrap();
+        assert!(doc.is_directory());
+        let variables: Vec<&str> = doc.variables().collect();
+        assert_eq!(variables, ["module", "name", "version"]);
+        assert_eq!(doc.tree()[2].kind(), VariableKind::Number);
+        assert!(doc.metadata("name").is_some());
+        let data = json!({"module": "core", "name": "demo", "version": 2});
+        let output = dir.path().join("output");
+        doc.render_to_dir(&output, &data).unwrap();
+        let files = list_files(&output).unwrap();
+        let files: Vec<String> = files
+            .iter()
+            .map(|f| relative_name(&output, f).unwrap())
+            .collect();
+        assert_eq!(files, ["LICENSE", "README.md", "src/core/demo.rs"]);
+        let contents = fs::read_to_string(output.join("src/core/demo.rs")).unwrap();
+        assert_eq!(contents, "// demo 2\n");
+        let contents = fs::read_to_string(output.join("LICENSE")).unwrap();
+        assert_eq!(contents, "{{not rendered}}");
+        let preview = doc.render_to_string(&data).unwrap();
+        assert_eq!(
+            preview,
+            "==> README.md <==\n# demo\n\n==> src/core/demo.rs <==\n// demo 2\n\n"
+        );
+        let mut doc = doc;
+        doc.set_strict(true);
+        let error = doc.render_to_dir(dir.path().join("strict"), &json!({"name": "x"}));
+        assert!(matches!(error, Err(Error::Render(_))));
+        assert!(!dir.path().join("strict").exists());
+        doc.set_strict(false);
+        let data = json!({"module": "..", "name": "demo", "version": 2});
+        let error = doc.render_to_dir(dir.path().join("escaped"), &data);
+        assert!(matches!(error, Err(Error::InvalidFileName(_))));
+        let data = json!({"module": "core", "name": "O'Brien & Sons", "version": 2});
+        doc.render_to_dir(&output, &data).unwrap();
+        assert!(output.join("src/core/O'Brien & Sons.rs").is_file());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn list_linked_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("skeleton");
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("src/main.rs.hbs"), "{{name}}").unwrap();
+        std::os::unix::fs::symlink(&root, root.join("src/loop")).unwrap();
+        let files = list_files(&root).unwrap();
+        assert_eq!(files, [PathBuf::from("src/main.rs.hbs")]);
+    }
+
+    #[test]
+    fn render_partials() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("partials/blog")).unwrap();
+        fs::write(dir.path().join("partials/header.hbs"), "# {{title}}\n").unwrap();
+        fs::write(
+            dir.path().join("partials/blog/card.hbs"),
+            "{{name}} ({{role}}){{#if ../draft}}*{{/if}}",
+        )
+        .unwrap();
+        let shared = tempfile::tempdir().unwrap();
+        fs::write(shared.path().join("footer.hbs"), "-- {{signature}}").unwrap();
+        fs::write(shared.path().join("header.hbs"), "ignored {{ignored}}").unwrap();
+        fs::write(shared.path().join("broken.hbs"), "{{#if draft}}").unwrap();
+        let path = dir.path().join("template-partials.txt.hbs");
+        fs::write(
+            &path,
+            "{{> header}}{{> blog/card author role=\"Author\"}}\n\
+             {{#> sidebar}}none{{/sidebar}}{{> footer}}",
+        )
+        .unwrap();
+        let doc = Document::open(&path, &[shared.path().into()]).unwrap();
+        let variables: Vec<&str> = doc.variables().collect();
+        assert_eq!(variables, ["author.name", "draft", "signature", "title"]);
+        let data = json!({"title": "News", "author": {"name": "Jane"}, "signature": "J."});
+        let output = doc.render_to_string(&data).unwrap();
+        assert_eq!(output, "# News\nJane (Author)\nnone-- J.");
+        let error = Document::open(&path, &[]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Partial not found in the partials directories: footer"
+        );
+        fs::write(&path, "{{> broken}}").unwrap();
+        let error = Document::open(&path, &[shared.path().into()]).unwrap_err();
+        assert!(matches!(error, Error::Template(_)));
+    }
+
+    #[test]
+    fn render_script_helpers() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("helpers")).unwrap();
+        fs::write(
+            dir.path().join("helpers/reference.rhai"),
+            r#"`${hash.prefix}-${params[0].to_upper()}`"#,
+        )
+        .unwrap();
+        fs::write(dir.path().join("helpers/year.rhai"), "2024").unwrap();
+        fs::write(
+            dir.path().join("order.txt.hbs"),
+            "{{reference id prefix=\"ORD\"}} {{year}} {{this.year}}",
+        )
+        .unwrap();
+        let doc = Document::open(dir.path(), &[]).unwrap();
+        let variables: Vec<&str> = doc.variables().collect();
+        assert_eq!(variables, ["id", "year"]);
+        let output = doc
+            .render_to_string(&json!({"id": "ab", "year": 2025}))
+            .unwrap();
+        assert_eq!(output, "==> order.txt <==\nORD-AB 2024 2025\n");
+        fs::write(dir.path().join("helpers/year.rhai"), "let x = ;").unwrap();
+        let error = Document::open(dir.path(), &[]).unwrap_err();
+        assert!(matches!(error, Error::Script(name, _) if name == "year"));
+        fs::remove_file(dir.path().join("helpers/year.rhai")).unwrap();
+        fs::write(dir.path().join("helpers/upper.rhai"), "params[0]").unwrap();
+        let error = Document::open(dir.path(), &[]).unwrap_err();
+        assert!(matches!(error, Error::Script(name, _) if name == "upper"));
+    }
+
+    #[test]
+    fn render_nested() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("template-nested.txt.hbs");
+        let mut input = File::create(&path).unwrap();
+        write!(input, "{{{{author.name}}}} ({{{{year}}}})").unwrap();
+        let doc = Document::open(path, &[]).unwrap();
+        let data = build_data([("author.name", "Jane"), ("year", "2025")]);
+        let output = dir.path().join("output.txt");
+        doc.render_to_file(&output, &data).unwrap();
+        let contents = std::fs::read_to_string(output).unwrap();
+        assert_eq!(contents, "Jane (2025)");
+        assert_eq!(doc.render_to_string(&data).unwrap(), contents);
+    }
 }
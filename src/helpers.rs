@@ -0,0 +1,294 @@
+//
+// This file is part of template-filler
+//
+// SPDX-FileCopyrightText: © 2024 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+use crate::data;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime};
+use handlebars::{
+    handlebars_helper, Context, Handlebars, Helper, HelperResult, JsonTruthy, Output,
+    RenderContext, RenderErrorReason,
+};
+use heck::{ToKebabCase, ToLowerCamelCase, ToSnakeCase, ToUpperCamelCase};
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::Engine;
+use serde_json::Value;
+use std::fmt::{Display, Write};
+use tracing::debug;
+
+/// Names of the helpers registered in addition to the built-in ones.
+///
+/// The comparison and logic helpers (`eq`, `ne`, `and`, `or`, `not`, ...)
+/// are built into Handlebars.
+pub const HELPERS: &[&str] = &[
+    "upper",
+    "lower",
+    "snake_case",
+    "kebab_case",
+    "camel_case",
+    "pascal_case",
+    "trim",
+    "replace",
+    "default",
+    "date",
+    "now",
+    "join",
+    "pluralize",
+];
+
+/// Names of the helpers built into Handlebars.
+pub const BUILTIN_HELPERS: &[&str] = &[
+    "if", "unless", "each", "with", "lookup", "raw", "log", "eq", "ne", "gt", "gte", "lt", "lte",
+    "and", "or", "not", "len",
+];
+
+/// Default format of the `date` and `now` helpers.
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Directory holding the script helpers, next to a template or in a directory of templates.
+pub const SCRIPTS_DIR: &str = "helpers";
+/// Suffix of the names of the script helpers.
+pub const SCRIPT_SUFFIX: &str = ".rhai";
+/// Maximum number of operations run by a script helper, to stop endless loops.
+const SCRIPT_MAX_OPERATIONS: u64 = 1_000_000;
+/// Maximum size of the strings and arrays built by a script helper.
+const SCRIPT_MAX_SIZE: usize = 1 << 20;
+
+handlebars_helper!(upper: |value: Json| data::to_text(value).to_uppercase());
+handlebars_helper!(lower: |value: Json| data::to_text(value).to_lowercase());
+handlebars_helper!(snake_case: |value: Json| data::to_text(value).to_snake_case());
+handlebars_helper!(kebab_case: |value: Json| data::to_text(value).to_kebab_case());
+handlebars_helper!(camel_case: |value: Json| data::to_text(value).to_lower_camel_case());
+handlebars_helper!(pascal_case: |value: Json| data::to_text(value).to_upper_camel_case());
+handlebars_helper!(trim: |value: Json| data::to_text(value).trim().to_string());
+handlebars_helper!(replace: |value: Json, from: str, to: str| {
+    data::to_text(value).replace(from, to)
+});
+handlebars_helper!(default: |*args| {
+    match args.as_slice() {
+        [value, ..] if value.is_truthy(false) => (*value).clone(),
+        [_, fallback, ..] => (*fallback).clone(),
+        _ => Value::Null,
+    }
+});
+
+/// Register the helpers on `handlebars`.
+pub fn register(handlebars: &mut Handlebars) {
+    handlebars.register_helper("upper", Box::new(upper));
+    handlebars.register_helper("lower", Box::new(lower));
+    handlebars.register_helper("snake_case", Box::new(snake_case));
+    handlebars.register_helper("kebab_case", Box::new(kebab_case));
+    handlebars.register_helper("camel_case", Box::new(camel_case));
+    handlebars.register_helper("pascal_case", Box::new(pascal_case));
+    handlebars.register_helper("trim", Box::new(trim));
+    handlebars.register_helper("replace", Box::new(replace));
+    handlebars.register_helper("default", Box::new(default));
+    handlebars.register_helper("date", Box::new(date));
+    handlebars.register_helper("now", Box::new(now));
+    handlebars.register_helper("join", Box::new(join));
+    handlebars.register_helper("pluralize", Box::new(pluralize));
+}
+
+/// Create the engine running the script helpers.
+///
+/// The scripts can not import modules, nor access files or the network, and
+/// the resources they use are limited.
+pub fn script_engine() -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .set_module_resolver(DummyModuleResolver::new())
+        .set_max_modules(0)
+        .set_max_operations(SCRIPT_MAX_OPERATIONS)
+        .set_max_call_levels(32)
+        .set_max_expr_depths(64, 32)
+        .set_max_string_size(SCRIPT_MAX_SIZE)
+        .set_max_array_size(SCRIPT_MAX_SIZE)
+        .set_max_map_size(SCRIPT_MAX_SIZE)
+        .on_print(|text| debug!("Script: {}", text))
+        .on_debug(|text, _, position| debug!("Script ({}): {}", position, text));
+    engine
+}
+
+/// Return the text of the parameter of `helper` at `index`, if any.
+fn param_text(helper: &Helper, index: usize) -> Option<String> {
+    helper.param(index).map(|p| data::to_text(p.value()))
+}
+
+/// Write the formatted date `value`, failing on invalid specifiers instead of panicking.
+fn write_formatted(out: &mut dyn Output, value: impl Display) -> HelperResult {
+    let mut text = String::new();
+    write!(text, "{}", value)
+        .map_err(|_| RenderErrorReason::Other("Invalid date format".to_string()))?;
+    out.write(&text)?;
+    Ok(())
+}
+
+/// `{{date value [format]}}`: format a date, given as `YYYY-MM-DD` or RFC 3339.
+///
+/// An empty value renders as nothing.
+fn date(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let value = param_text(h, 0).unwrap_or_default();
+    let format = param_text(h, 1).unwrap_or_else(|| DATE_FORMAT.to_string());
+    if value.is_empty() {
+        return Ok(());
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(&value, "%Y-%m-%d") {
+        return write_formatted(out, date.format(&format));
+    }
+    if let Ok(date) = DateTime::parse_from_rfc3339(&value) {
+        return write_formatted(out, date.format(&format));
+    }
+    if let Ok(date) = NaiveDateTime::parse_from_str(&value, "%Y-%m-%d %H:%M:%S") {
+        return write_formatted(out, date.format(&format));
+    }
+    Err(RenderErrorReason::Other(format!("Invalid date: {}", value)).into())
+}
+
+/// `{{now [format]}}`: format the current local date and time.
+fn now(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let format = param_text(h, 0).unwrap_or_else(|| DATE_FORMAT.to_string());
+    write_formatted(out, Local::now().format(&format))
+}
+
+/// `{{join items [separator]}}`: join the items of a list, with `, ` by default.
+fn join(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let separator = param_text(h, 1).unwrap_or_else(|| ", ".to_string());
+    let text = match h.param(0).map(|p| p.value()) {
+        Some(Value::Array(items)) => items
+            .iter()
+            .map(data::to_text)
+            .collect::<Vec<_>>()
+            .join(&separator),
+        Some(value) => data::to_text(value),
+        None => String::new(),
+    };
+    out.write(&text)?;
+    Ok(())
+}
+
+/// `{{pluralize count singular [plural]}}`: pick the word matching `count`.
+///
+/// The plural defaults to the singular followed by `s`.
+fn pluralize(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let count = match h.param(0).map(|p| p.value()) {
+        Some(Value::Number(n)) => n.as_f64(),
+        Some(Value::Array(items)) => Some(items.len() as f64),
+        Some(value) => data::to_text(value).trim().parse().ok(),
+        None => None,
+    };
+    let count = count.ok_or(RenderErrorReason::ParamNotFoundForIndex("pluralize", 0))?;
+    let singular =
+        param_text(h, 1).ok_or(RenderErrorReason::ParamNotFoundForIndex("pluralize", 1))?;
+    let word = if count == 1.0 {
+        singular
+    } else {
+        param_text(h, 2).unwrap_or_else(|| format!("{}s", singular))
+    };
+    out.write(&word)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn render(template: &str, data: &Value) -> String {
+        let mut handlebars = Handlebars::new();
+        register(&mut handlebars);
+        handlebars.render_template(template, data).unwrap()
+    }
+
+    #[test]
+    fn render_helpers() {
+        let data = json!({
+            "name": "  Order Form ",
+            "tags": ["a", "b"],
+            "count": "2",
+            "day": "2024-02-29",
+            "empty": "",
+        });
+        assert_eq!(render("{{upper name}}", &data), "  ORDER FORM ");
+        assert_eq!(render("{{lower (trim name)}}", &data), "order form");
+        assert_eq!(render("{{snake_case name}}", &data), "order_form");
+        assert_eq!(render("{{kebab_case name}}", &data), "order-form");
+        assert_eq!(render("{{camel_case name}}", &data), "orderForm");
+        assert_eq!(render("{{pascal_case name}}", &data), "OrderForm");
+        assert_eq!(
+            render("{{replace name \"Order\" \"Quote\"}}", &data),
+            "  Quote Form "
+        );
+        assert_eq!(render("{{default empty \"none\"}}", &data), "none");
+        assert_eq!(render("{{default missing \"none\"}}", &data), "none");
+        assert_eq!(render("{{date day \"%d/%m/%Y\"}}", &data), "29/02/2024");
+        assert_eq!(render("{{date empty}}", &data), "");
+        assert_eq!(render("{{join tags \" | \"}}", &data), "a | b");
+        assert_eq!(
+            render("{{count}} {{pluralize count \"box\" \"boxes\"}}", &data),
+            "2 boxes"
+        );
+        assert_eq!(
+            render(
+                "{{pluralize 1 \"item\"}}, {{pluralize tags \"tag\"}}",
+                &data
+            ),
+            "item, tags"
+        );
+        assert_eq!(
+            render(
+                "{{#if (and (eq count \"2\") (not empty))}}yes{{/if}}",
+                &data
+            ),
+            "yes"
+        );
+        assert_eq!(render("{{now}}", &data).len(), 10);
+        let mut handlebars = Handlebars::new();
+        register(&mut handlebars);
+        assert!(handlebars.render_template("{{date name}}", &data).is_err());
+    }
+
+    #[test]
+    fn run_scripts() {
+        let mut handlebars = Handlebars::new();
+        handlebars.set_engine(script_engine());
+        let script = r#"params[0].to_upper() + "-" + hash.sep"#;
+        handlebars.register_script_helper("tag", script).unwrap();
+        handlebars
+            .register_script_helper("spin", "loop { }")
+            .unwrap();
+        let import = r#"import "secret" as s; s::x"#;
+        handlebars.register_script_helper("read", import).unwrap();
+        let data = json!({"name": "ab"});
+        let output = handlebars.render_template(r#"{{tag name sep="x"}}"#, &data);
+        assert_eq!(output.unwrap(), "AB-x");
+        assert!(handlebars.render_template("{{spin}}", &data).is_err());
+        assert!(handlebars.render_template("{{read}}", &data).is_err());
+    }
+}
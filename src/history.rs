@@ -0,0 +1,70 @@
+//
+// This file is part of template-filler
+//
+// SPDX-FileCopyrightText: © 2024 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+use crate::document::Error;
+use gtk::glib;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// Values last used for each template, stored as JSON in the data directory.
+///
+/// The templates are identified by their canonical path.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct History {
+    values: BTreeMap<PathBuf, Value>,
+}
+
+impl History {
+    /// Return the path of the file holding the values.
+    fn path() -> PathBuf {
+        glib::user_data_dir()
+            .join("template-filler")
+            .join("values.json")
+    }
+    /// Return the key identifying the template at `path`.
+    pub fn key(path: &Path) -> PathBuf {
+        fs::canonicalize(path).unwrap_or_else(|_| path.into())
+    }
+    /// Load the values, falling back to none if they can not be read.
+    pub fn load() -> Self {
+        let path = Self::path();
+        match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|error| {
+                warn!("Invalid saved values in {}: {}", path.display(), error);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+    /// Save the values.
+    pub fn save(&self) -> Result<(), Error> {
+        let path = Self::path();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+    /// Return the values last used for the template at `path`, if any.
+    pub fn get(&self, path: &Path) -> Option<&Value> {
+        self.values.get(&Self::key(path))
+    }
+    /// Set the values last used for the template at `path`.
+    pub fn set(&mut self, path: &Path, data: Value) {
+        self.values.insert(Self::key(path), data);
+    }
+    /// Forget the values used for the template at `path`.
+    pub fn remove(&mut self, path: &Path) {
+        self.values.remove(&Self::key(path));
+    }
+}
@@ -7,21 +7,44 @@
 //
 
 mod application;
+mod batch;
+mod cli;
 mod config;
+mod data;
 mod document;
+mod helpers;
+mod history;
+mod metadata;
+mod profiles;
+mod recent;
+mod session;
+mod settings;
+mod validation;
 mod variable;
 mod widgets;
 mod window;
 
+use clap::Parser;
 use gtk::{gio, glib, prelude::*};
 
 fn main() -> glib::ExitCode {
-    tracing_subscriber::fmt::init();
+    // The rendered document may be written to the standard output.
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .init();
+
+    let cli = cli::Cli::parse();
+    if let Some(command) = cli.command {
+        return command.run();
+    }
 
     gio::resources_register_include!("resources.gresource").expect("Resources should be available");
 
     glib::set_application_name("TemplateFiller");
 
     let app = application::TemplateFiller::default();
-    app.run()
+    let args = std::env::args()
+        .take(1)
+        .chain(cli.files.iter().map(|f| f.to_string_lossy().into_owned()));
+    app.run_with_args(&args.collect::<Vec<_>>())
 }
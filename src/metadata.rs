@@ -0,0 +1,249 @@
+//
+// This file is part of template-filler
+//
+// SPDX-FileCopyrightText: © 2024 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+use crate::document::{Error, VariableKind};
+use crate::validation::ValueFormat;
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Information about the variables of a template, declared by its author.
+///
+/// It is read from a YAML front matter block at the top of the template,
+/// or from a sidecar file named after the template, e.g. `letter.hbs.yml`.
+#[derive(Debug, Default, Clone, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Metadata {
+    /// Information about each variable, by dotted path.
+    pub variables: BTreeMap<String, VariableMeta>,
+}
+
+/// Information about a variable of a template.
+#[derive(Debug, Default, Clone, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct VariableMeta {
+    /// Name to display instead of the one used in the template.
+    pub label: Option<String>,
+    /// Explanation of what the variable is for.
+    pub description: Option<String>,
+    /// Value set when opening the template.
+    pub default: Option<Value>,
+    /// Kind of value, overriding the one inferred from the template.
+    #[serde(rename = "type")]
+    pub kind: Option<VariableKind>,
+    /// Whether the template can only be rendered once the variable has a value.
+    pub required: bool,
+    /// Whether the value usually spans several lines.
+    pub multiline: bool,
+    /// Regular expression the value must match.
+    pub pattern: Option<String>,
+    /// Values allowed for the variable.
+    #[serde(rename = "enum", deserialize_with = "deserialize_choices")]
+    pub choices: Vec<Value>,
+    /// Minimum number of characters of the value.
+    pub min_length: Option<usize>,
+    /// Maximum number of characters of the value.
+    pub max_length: Option<usize>,
+    /// Minimum numeric value.
+    pub min: Option<f64>,
+    /// Maximum numeric value.
+    pub max: Option<f64>,
+    /// Well-known format of the value.
+    pub format: Option<ValueFormat>,
+}
+
+impl Metadata {
+    /// Parse metadata from its YAML `text`.
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        if text.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        Ok(serde_yaml::from_str(text)?)
+    }
+    /// Load the metadata of the template at `path`, whose contents are `contents`.
+    ///
+    /// The front matter of the template takes precedence over the sidecar file.
+    pub fn load(path: &Path, contents: &str) -> Result<Self, Error> {
+        if let (Some(front_matter), _, _) = split_front_matter(contents) {
+            return Self::parse(front_matter);
+        }
+        let sidecar = sidecar_path(path);
+        if sidecar.is_file() {
+            return Self::parse(&fs::read_to_string(sidecar)?);
+        }
+        Ok(Self::default())
+    }
+    /// Add the information from `other` about the variables not known yet.
+    pub fn merge(&mut self, other: Metadata) {
+        for (path, meta) in other.variables {
+            self.variables.entry(path).or_insert(meta);
+        }
+    }
+    /// Return the information about the variable at dotted `path`, if any.
+    pub fn get(&self, path: &str) -> Option<&VariableMeta> {
+        self.variables.get(path)
+    }
+}
+
+/// Deserialize the values allowed for a variable, which may be numbers or booleans too.
+fn deserialize_choices<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Value>, D::Error> {
+    let choices = Vec::<Value>::deserialize(deserializer)?;
+    match choices
+        .iter()
+        .find(|value| !matches!(value, Value::String(_) | Value::Bool(_) | Value::Number(_)))
+    {
+        Some(value) => Err(serde::de::Error::custom(format!(
+            "invalid value in enum: {}",
+            value
+        ))),
+        None => Ok(choices),
+    }
+}
+
+/// Return the path of the sidecar file holding the metadata of the template at `path`.
+pub fn sidecar_path(path: &Path) -> PathBuf {
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(".yml");
+    sidecar.into()
+}
+
+/// Split the YAML front matter delimited by `---` lines from the body of a template.
+///
+/// Return the front matter if any, the body and the number of lines before the body.
+/// A block which is not a YAML mapping with a `variables` key, like the first
+/// document of a YAML template, is part of the body.
+pub fn split_front_matter(contents: &str) -> (Option<&str>, &str, usize) {
+    let Some(rest) = contents
+        .strip_prefix("---\n")
+        .or_else(|| contents.strip_prefix("---\r\n"))
+    else {
+        return (None, contents, 0);
+    };
+    let mut start = 0;
+    let mut lines = 1;
+    for line in rest.split_inclusive('\n') {
+        lines += 1;
+        if line.trim_end() == "---" {
+            let front_matter = &rest[..start];
+            if !declares_variables(front_matter) {
+                break;
+            }
+            return (Some(front_matter), &rest[start + line.len()..], lines);
+        }
+        start += line.len();
+    }
+    (None, contents, 0)
+}
+
+/// Return `true` if `text` is a YAML mapping with a top-level `variables` key.
+fn declares_variables(text: &str) -> bool {
+    serde_yaml::from_str::<serde_yaml::Mapping>(text)
+        .is_ok_and(|mapping| mapping.contains_key("variables"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const TEMPLATE: &str = r#"---
+variables:
+  ref_no:
+    label: Reference
+    description: Number of the order
+    required: true
+    multiline: false
+    pattern: "^[A-Z]-[0-9]+$"
+  env:
+    default: dev
+    enum: [dev, staging, prod]
+  amount:
+    type: number
+    default: 10
+  address:
+    multiline: true
+---
+Order {{ref_no}} ({{env}})
+"#;
+
+    #[test]
+    fn split_templates() {
+        let (front_matter, body, lines) = split_front_matter(TEMPLATE);
+        assert!(front_matter.unwrap().starts_with("variables:"));
+        assert_eq!(body, "Order {{ref_no}} ({{env}})\n");
+        assert_eq!(lines, 17);
+        let (front_matter, body, lines) = split_front_matter("--- {{title}}\n");
+        assert_eq!((front_matter, body, lines), (None, "--- {{title}}\n", 0));
+        let (front_matter, body, lines) = split_front_matter("---\nunterminated\n");
+        assert_eq!(
+            (front_matter, body, lines),
+            (None, "---\nunterminated\n", 0)
+        );
+        for contents in [
+            "---\nname: {{name}}\n---\nport: 80\n",
+            "---\n# Title\n---\nText\n",
+            "---\nvariables: [\n---\n",
+        ] {
+            assert_eq!(split_front_matter(contents), (None, contents, 0));
+        }
+    }
+
+    #[test]
+    fn parse_metadata() {
+        let (front_matter, _, _) = split_front_matter(TEMPLATE);
+        let metadata = Metadata::parse(front_matter.unwrap()).unwrap();
+        let ref_no = metadata.get("ref_no").unwrap();
+        assert_eq!(ref_no.label.as_deref(), Some("Reference"));
+        assert!(ref_no.required);
+        assert_eq!(ref_no.pattern.as_deref(), Some("^[A-Z]-[0-9]+$"));
+        let env = metadata.get("env").unwrap();
+        assert_eq!(env.default, Some(json!("dev")));
+        assert_eq!(env.choices, vec!["dev", "staging", "prod"]);
+        let amount = metadata.get("amount").unwrap();
+        assert_eq!(amount.kind, Some(VariableKind::Number));
+        assert!(metadata.get("address").unwrap().multiline);
+        assert!(!ref_no.multiline);
+        assert!(metadata.get("missing").is_none());
+        assert!(Metadata::parse("variables:\n  x:\n    lable: X\n").is_err());
+        let metadata = Metadata::parse("variables:\n  level:\n    enum: [1, 2.5, true]\n").unwrap();
+        assert_eq!(
+            metadata.get("level").unwrap().choices,
+            [json!(1), json!(2.5), json!(true)]
+        );
+        assert!(Metadata::parse("variables:\n  x:\n    enum: [[1]]\n").is_err());
+    }
+
+    #[test]
+    fn load_sidecar() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("letter.txt.hbs");
+        fs::write(&path, "Dear {{name}}").unwrap();
+        assert_eq!(
+            Metadata::load(&path, "Dear {{name}}").unwrap(),
+            Metadata::default()
+        );
+        fs::write(
+            sidecar_path(&path),
+            "variables:\n  name:\n    label: Recipient\n",
+        )
+        .unwrap();
+        let metadata = Metadata::load(&path, "Dear {{name}}").unwrap();
+        assert_eq!(
+            metadata.get("name").unwrap().label.as_deref(),
+            Some("Recipient")
+        );
+        let metadata = Metadata::load(&path, "---\nvariables: {}\n---\nDear {{name}}").unwrap();
+        assert_eq!(metadata, Metadata::default());
+        let metadata = Metadata::load(&path, "---\nauthor: Jane\n---\nDear {{name}}").unwrap();
+        assert!(metadata.get("name").is_some());
+        let metadata = Metadata::parse("title: Letter\nvariables: {}\n").unwrap();
+        assert_eq!(metadata, Metadata::default());
+    }
+}
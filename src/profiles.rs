@@ -0,0 +1,213 @@
+//
+// This file is part of template-filler
+//
+// SPDX-FileCopyrightText: © 2024 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+use crate::data::{self, Format};
+use crate::document::Error;
+use serde_json::Value;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Suffix of the directory holding the profiles of a template, next to it.
+pub const PROFILES_SUFFIX: &str = ".profiles";
+
+/// Named sets of values for a template, stored as data files next to it.
+///
+/// The profiles of `invoice.txt.hbs` are in `invoice.txt.hbs.profiles/`, one file
+/// per profile, named after it. New profiles are saved as JSON, but YAML and TOML
+/// files are read too.
+#[derive(Debug, Clone)]
+pub struct Profiles {
+    dir: PathBuf,
+}
+
+impl Profiles {
+    /// Return the profiles of the template at `path`.
+    ///
+    /// The profiles of a directory given as `site/` or `.` are next to it too.
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref();
+        let path = match path.file_name() {
+            Some(_) => path.to_path_buf(),
+            None => fs::canonicalize(path).unwrap_or_else(|_| path.into()),
+        };
+        let mut name = path.file_name().unwrap_or_default().to_os_string();
+        name.push(PROFILES_SUFFIX);
+        Self {
+            dir: path.with_file_name(name),
+        }
+    }
+
+    /// Return the names of the profiles, sorted.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(&self.dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file() && Format::from_path(path).is_some())
+            .filter_map(|path| {
+                path.file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .map(decode_name)
+            })
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Return the path of the file holding the profile `name`, if it exists.
+    fn find(&self, name: &str) -> Option<PathBuf> {
+        let stem = encode_name(name);
+        ["json", "yaml", "yml", "toml"]
+            .iter()
+            .map(|extension| self.dir.join(format!("{}.{}", stem, extension)))
+            .find(|path| path.is_file())
+    }
+
+    /// Load the values of the profile `name`.
+    pub fn load(&self, name: &str) -> Result<Value, Error> {
+        let path = self
+            .find(name)
+            .ok_or_else(|| Error::UnknownProfile(name.to_string()))?;
+        data::load(path)
+    }
+
+    /// Save `data` as the values of the profile `name`, replacing the previous ones.
+    pub fn save(&self, name: &str, data: &Value) -> Result<(), Error> {
+        let path = match self.find(name) {
+            Some(path) => path,
+            None => {
+                fs::create_dir_all(&self.dir)?;
+                self.dir.join(format!("{}.json", encode_name(name)))
+            }
+        };
+        data::save(path, data)
+    }
+
+    /// Rename the profile `name` to `new_name`, which must not be used yet.
+    pub fn rename(&self, name: &str, new_name: &str) -> Result<(), Error> {
+        if name == new_name {
+            return Ok(());
+        }
+        if self.find(new_name).is_some() {
+            return Err(Error::DuplicateProfile(new_name.to_string()));
+        }
+        let path = self
+            .find(name)
+            .ok_or_else(|| Error::UnknownProfile(name.to_string()))?;
+        let mut file_name = OsString::from(encode_name(new_name));
+        if let Some(extension) = path.extension() {
+            file_name.push(".");
+            file_name.push(extension);
+        }
+        fs::rename(&path, self.dir.join(file_name))?;
+        Ok(())
+    }
+
+    /// Delete the profile `name`.
+    pub fn remove(&self, name: &str) -> Result<(), Error> {
+        let path = self
+            .find(name)
+            .ok_or_else(|| Error::UnknownProfile(name.to_string()))?;
+        fs::remove_file(path)?;
+        Ok(())
+    }
+}
+
+/// Return the file stem for the profile `name`, escaping the characters not allowed.
+fn encode_name(name: &str) -> String {
+    let mut stem = String::new();
+    for (index, c) in name.chars().enumerate() {
+        match c {
+            '%' => stem.push_str("%25"),
+            '/' => stem.push_str("%2F"),
+            '\\' => stem.push_str("%5C"),
+            '.' if index == 0 => stem.push_str("%2E"),
+            c => stem.push(c),
+        }
+    }
+    stem
+}
+
+/// Return the name of the profile stored with the file stem `stem`.
+fn decode_name(stem: &str) -> String {
+    stem.replace("%2F", "/")
+        .replace("%5C", "\\")
+        .replace("%2E", ".")
+        .replace("%25", "%")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::fs::File;
+
+    #[test]
+    fn encode_names() {
+        for name in ["ACME / prod", "50% off", ".hidden", "a\\b", "%2F"] {
+            assert_eq!(decode_name(&encode_name(name)), name);
+            assert!(!encode_name(name).contains('/'));
+        }
+    }
+
+    #[test]
+    fn manage_profiles() {
+        let dir = tempfile::tempdir().unwrap();
+        let template = dir.path().join("invoice.txt.hbs");
+        File::create(&template).unwrap();
+        let profiles = Profiles::new(&template);
+        assert!(profiles.names().is_empty());
+        assert!(matches!(
+            profiles.load("ACME / prod"),
+            Err(Error::UnknownProfile(_))
+        ));
+
+        let acme = json!({"customer": "ACME", "env": "prod"});
+        let globex = json!({"customer": "Globex", "env": "staging"});
+        profiles.save("ACME / prod", &acme).unwrap();
+        profiles.save("Globex / staging", &globex).unwrap();
+        assert!(dir
+            .path()
+            .join("invoice.txt.hbs.profiles/ACME %2F prod.json")
+            .is_file());
+        assert_eq!(profiles.names(), ["ACME / prod", "Globex / staging"]);
+        assert_eq!(profiles.load("ACME / prod").unwrap(), acme);
+
+        assert!(matches!(
+            profiles.rename("ACME / prod", "Globex / staging"),
+            Err(Error::DuplicateProfile(_))
+        ));
+        profiles.rename("ACME / prod", "ACME / test").unwrap();
+        assert_eq!(profiles.names(), ["ACME / test", "Globex / staging"]);
+        assert_eq!(profiles.load("ACME / test").unwrap(), acme);
+
+        profiles.remove("Globex / staging").unwrap();
+        assert_eq!(profiles.names(), ["ACME / test"]);
+    }
+
+    #[test]
+    fn load_other_formats() {
+        let dir = tempfile::tempdir().unwrap();
+        let template = dir.path().join("site");
+        let profiles = Profiles::new(&template);
+        fs::create_dir_all(dir.path().join("site.profiles")).unwrap();
+        fs::write(dir.path().join("site.profiles/dev.yaml"), "env: dev\n").unwrap();
+        assert_eq!(profiles.names(), ["dev"]);
+        fs::create_dir(&template).unwrap();
+        assert_eq!(Profiles::new(template.join("")).names(), ["dev"]);
+        assert_eq!(Profiles::new(template.join(".")).names(), ["dev"]);
+        profiles.save("dev", &json!({"env": "test"})).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.path().join("site.profiles/dev.yaml")).unwrap(),
+            "env: test\n"
+        );
+    }
+}
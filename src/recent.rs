@@ -0,0 +1,71 @@
+//
+// This file is part of template-filler
+//
+// SPDX-FileCopyrightText: © 2024 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+use crate::document::Error;
+use gtk::glib;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// Maximum number of recently opened templates to remember.
+const MAX_RECENT_FILES: usize = 10;
+
+/// Templates opened recently, most recent first, stored as TOML in the data directory.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RecentFiles {
+    files: Vec<PathBuf>,
+}
+
+impl RecentFiles {
+    /// Return the path of the file holding the list.
+    fn path() -> PathBuf {
+        glib::user_data_dir()
+            .join("template-filler")
+            .join("recent.toml")
+    }
+    /// Load the list, dropping the templates which no longer exist.
+    pub fn load() -> Self {
+        let path = Self::path();
+        let mut recent: Self = match fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents).unwrap_or_else(|error| {
+                warn!("Invalid recent files in {}: {}", path.display(), error);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        };
+        recent.prune();
+        recent
+    }
+    /// Save the list.
+    pub fn save(&self) -> Result<(), Error> {
+        let path = Self::path();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, toml::to_string(self)?)?;
+        Ok(())
+    }
+    /// Return the templates, most recent first.
+    pub fn files(&self) -> &[PathBuf] {
+        self.files.as_slice()
+    }
+    /// Move the template at `path` to the top of the list.
+    pub fn add(&mut self, path: &Path) {
+        self.files.retain(|file| file != path);
+        self.files.insert(0, path.into());
+        self.files.truncate(MAX_RECENT_FILES);
+    }
+    /// Drop the templates which no longer exist, returning `true` if any was dropped.
+    pub fn prune(&mut self) -> bool {
+        let count = self.files.len();
+        self.files.retain(|file| file.exists());
+        self.files.len() != count
+    }
+}
@@ -0,0 +1,82 @@
+//
+// This file is part of template-filler
+//
+// SPDX-FileCopyrightText: © 2024 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+use crate::document::VariableKind;
+use gtk::{glib, glib::Properties, prelude::*, subclass::prelude::*};
+use serde_json::Value;
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+
+mod imp {
+    use super::*;
+
+    #[derive(Debug, Default, Properties)]
+    #[properties(wrapper_type = super::SessionValue)]
+    pub struct SessionValue {
+        #[property(get, set)]
+        pub(crate) value: RefCell<String>,
+        pub(crate) kind: Cell<VariableKind>,
+        pub(crate) choices: RefCell<Vec<Value>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for SessionValue {
+        const NAME: &'static str = "SessionValue";
+        type Type = super::SessionValue;
+    }
+
+    #[glib::derived_properties]
+    impl ObjectImpl for SessionValue {}
+}
+
+glib::wrapper! {
+    /// Value shared by the variables with the same path in the open documents.
+    pub struct SessionValue(ObjectSubclass<imp::SessionValue>);
+}
+
+impl SessionValue {
+    /// Create a value shared by variables of `kind`, among `choices` for a choice.
+    fn new(kind: VariableKind, choices: &[Value]) -> Self {
+        let value: Self = glib::Object::new();
+        value.imp().kind.set(kind);
+        value.imp().choices.replace(choices.to_vec());
+        value
+    }
+
+    /// Return `true` if the value can be shared by a variable of `kind` with `choices`.
+    fn accepts(&self, kind: VariableKind, choices: &[Value]) -> bool {
+        self.imp().kind.get() == kind
+            && (kind != VariableKind::Choice || *self.imp().choices.borrow() == choices)
+    }
+}
+
+/// Values shared by the variables of the open documents, by dotted path.
+///
+/// A value is only held by the variables sharing it, and is dropped with the
+/// last of them.
+#[derive(Debug, Default)]
+pub struct Session {
+    values: RefCell<BTreeMap<String, glib::WeakRef<SessionValue>>>,
+}
+
+impl Session {
+    /// Return the value shared by the variables at `path` of `kind`, creating it if needed.
+    ///
+    /// There is none if the variables sharing the value are of another kind, or
+    /// allow other choices.
+    pub fn value(&self, path: &str, kind: VariableKind, choices: &[Value]) -> Option<SessionValue> {
+        let mut values = self.values.borrow_mut();
+        values.retain(|_, value| value.upgrade().is_some());
+        if let Some(value) = values.get(path).and_then(glib::WeakRef::upgrade) {
+            return value.accepts(kind, choices).then_some(value);
+        }
+        let value = SessionValue::new(kind, choices);
+        values.insert(path.to_string(), value.downgrade());
+        Some(value)
+    }
+}
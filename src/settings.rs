@@ -0,0 +1,72 @@
+//
+// This file is part of template-filler
+//
+// SPDX-FileCopyrightText: © 2024 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+use crate::document::Error;
+use gtk::glib;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::PathBuf;
+use tracing::warn;
+
+/// Preferences of the user, stored as TOML in the configuration directory.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Refuse to render a template while some variables are unset.
+    pub strict: bool,
+    /// Directories searched for partials, after the one next to the template.
+    pub partials_dirs: Vec<PathBuf>,
+    /// Templates whose values are not restored when opening them again, by canonical path.
+    pub private_templates: BTreeSet<PathBuf>,
+    /// Set the variables from the environment variables when opening a template.
+    pub import_env: bool,
+    /// Prefix of the environment variables holding the values of the variables.
+    pub env_prefix: String,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            strict: false,
+            partials_dirs: Vec::new(),
+            private_templates: BTreeSet::new(),
+            import_env: false,
+            env_prefix: String::from("TF_"),
+        }
+    }
+}
+
+impl Settings {
+    /// Return the path of the file holding the settings.
+    fn path() -> PathBuf {
+        glib::user_config_dir()
+            .join("template-filler")
+            .join("settings.toml")
+    }
+    /// Load the settings, falling back to the defaults if they can not be read.
+    pub fn load() -> Self {
+        let path = Self::path();
+        match fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents).unwrap_or_else(|error| {
+                warn!("Invalid settings in {}: {}", path.display(), error);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+    /// Save the settings.
+    pub fn save(&self) -> Result<(), Error> {
+        let path = Self::path();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, toml::to_string(self)?)?;
+        Ok(())
+    }
+}
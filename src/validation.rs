@@ -0,0 +1,317 @@
+//
+// This file is part of template-filler
+//
+// SPDX-FileCopyrightText: © 2024 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+use crate::data;
+use crate::document::Error;
+use crate::metadata::VariableMeta;
+use chrono::NaiveDate;
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+/// Well-known format of a text value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ValueFormat {
+    /// E-mail address, e.g. `jane@example.com`.
+    Email,
+    /// URL with a scheme, e.g. `https://example.com`.
+    Url,
+    /// Calendar date, as `YYYY-MM-DD`.
+    Date,
+}
+
+/// Reason why a value is not valid.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum Violation {
+    #[error("A value is required")]
+    Required,
+    #[error("The value must match {0}")]
+    Pattern(String),
+    #[error("The value must be one of: {}", .0.join(", "))]
+    Choice(Vec<String>),
+    #[error("The value must have at least {0} characters")]
+    TooShort(usize),
+    #[error("The value must have at most {0} characters")]
+    TooLong(usize),
+    #[error("The value must be a number")]
+    NotANumber,
+    #[error("The value must be at least {0}")]
+    TooSmall(f64),
+    #[error("The value must be at most {0}")]
+    TooLarge(f64),
+    #[error("The value must be an e-mail address")]
+    Email,
+    #[error("The value must be a URL")]
+    Url,
+    #[error("The value must be a date, as YYYY-MM-DD")]
+    Date,
+}
+
+/// Constraints on the value of a variable, declared in the metadata of a template.
+#[derive(Debug, Default, Clone)]
+pub struct Constraints {
+    required: bool,
+    pattern: Option<String>,
+    regex: Option<Regex>,
+    choices: Vec<String>,
+    min_length: Option<usize>,
+    max_length: Option<usize>,
+    min: Option<f64>,
+    max: Option<f64>,
+    format: Option<ValueFormat>,
+}
+
+impl Constraints {
+    /// Create the constraints declared by the metadata of a variable.
+    ///
+    /// The pattern must match the whole value.
+    pub fn from_meta(meta: &VariableMeta) -> Result<Self, Error> {
+        let regex = meta
+            .pattern
+            .as_ref()
+            .map(|pattern| Regex::new(&format!("^(?:{})$", pattern)))
+            .transpose()?;
+        Ok(Self {
+            required: meta.required,
+            pattern: meta.pattern.clone(),
+            regex,
+            choices: meta.choices.iter().map(data::to_text).collect(),
+            min_length: meta.min_length,
+            max_length: meta.max_length,
+            min: meta.min,
+            max: meta.max,
+            format: meta.format,
+        })
+    }
+
+    /// Check `text` against the constraints, an empty value being valid unless `required`.
+    pub fn validate(&self, text: &str, required: bool) -> Result<(), Violation> {
+        if text.is_empty() {
+            if required || self.required {
+                return Err(Violation::Required);
+            }
+            return Ok(());
+        }
+        if let (Some(pattern), Some(regex)) = (&self.pattern, &self.regex) {
+            if !regex.is_match(text) {
+                return Err(Violation::Pattern(pattern.clone()));
+            }
+        }
+        if !self.choices.is_empty() && !self.choices.iter().any(|c| c == text) {
+            return Err(Violation::Choice(self.choices.clone()));
+        }
+        let length = text.chars().count();
+        if let Some(min_length) = self.min_length.filter(|min| length < *min) {
+            return Err(Violation::TooShort(min_length));
+        }
+        if let Some(max_length) = self.max_length.filter(|max| length > *max) {
+            return Err(Violation::TooLong(max_length));
+        }
+        if self.min.is_some() || self.max.is_some() {
+            let number = text
+                .trim()
+                .parse::<f64>()
+                .map_err(|_| Violation::NotANumber)?;
+            if let Some(min) = self.min.filter(|min| number < *min) {
+                return Err(Violation::TooSmall(min));
+            }
+            if let Some(max) = self.max.filter(|max| number > *max) {
+                return Err(Violation::TooLarge(max));
+            }
+        }
+        match self.format {
+            Some(ValueFormat::Email) if !is_email(text) => Err(Violation::Email),
+            Some(ValueFormat::Url) if !is_url(text) => Err(Violation::Url),
+            Some(ValueFormat::Date) if !is_date(text) => Err(Violation::Date),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Check the values in `data` against the constraints on each dotted path.
+///
+/// The constraints on the fields of list items apply to every item.
+/// Return the concrete paths of the invalid values, with the reason.
+pub fn validate_data(
+    constraints: &BTreeMap<String, Constraints>,
+    data: &Value,
+) -> Vec<(String, Violation)> {
+    let mut violations = Vec::new();
+    for (path, constraints) in constraints {
+        let segments: Vec<&str> = path.split('.').collect();
+        let mut values = Vec::new();
+        lookup(data, &segments, String::new(), &mut values);
+        for (path, value) in values {
+            let text = value.map(data::to_text).unwrap_or_default();
+            if let Err(violation) = constraints.validate(&text, false) {
+                violations.push((path, violation));
+            }
+        }
+    }
+    violations
+}
+
+/// Collect the values at `segments` in `data`, going through arrays.
+fn lookup<'a>(
+    data: &'a Value,
+    segments: &[&str],
+    prefix: String,
+    values: &mut Vec<(String, Option<&'a Value>)>,
+) {
+    if let Value::Array(items) = data {
+        for (index, item) in items.iter().enumerate() {
+            lookup(item, segments, join(&prefix, &index.to_string()), values);
+        }
+        return;
+    }
+    let Some((first, rest)) = segments.split_first() else {
+        values.push((prefix, Some(data).filter(|v| !v.is_null())));
+        return;
+    };
+    let path = join(&prefix, first);
+    match data.get(first) {
+        Some(value) => lookup(value, rest, path, values),
+        None => values.push((path, None)),
+    }
+}
+
+fn join(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", prefix, name)
+    }
+}
+
+fn is_email(text: &str) -> bool {
+    match text.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && !domain.contains('@')
+                && domain.split('.').count() > 1
+                && domain.split('.').all(|part| !part.is_empty())
+                && !text.contains(char::is_whitespace)
+        }
+        None => false,
+    }
+}
+
+fn is_url(text: &str) -> bool {
+    match text.split_once("://") {
+        Some((scheme, rest)) => {
+            scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+                && !rest.is_empty()
+                && !rest.starts_with('/')
+                && !text.contains(char::is_whitespace)
+        }
+        None => false,
+    }
+}
+
+fn is_date(text: &str) -> bool {
+    // The parser accepts signs and single digits, hence the check of the layout.
+    let layout = text.char_indices().all(|(index, c)| match index {
+        4 | 7 => c == '-',
+        _ => c.is_ascii_digit(),
+    });
+    text.len() == 10 && layout && NaiveDate::parse_from_str(text, "%Y-%m-%d").is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn constraints(yaml: &str) -> Constraints {
+        let meta: VariableMeta = serde_yaml::from_str(yaml).unwrap();
+        Constraints::from_meta(&meta).unwrap()
+    }
+
+    #[test]
+    fn validate_values() {
+        let c = constraints("required: true\npattern: '[A-Z]-[0-9]+'");
+        assert_eq!(c.validate("", false), Err(Violation::Required));
+        assert_eq!(c.validate("A-42", false), Ok(()));
+        assert_eq!(
+            c.validate("A-42x", false),
+            Err(Violation::Pattern("[A-Z]-[0-9]+".to_string()))
+        );
+        let c = constraints("min_length: 2\nmax_length: 4");
+        assert_eq!(c.validate("", false), Ok(()));
+        assert_eq!(c.validate("", true), Err(Violation::Required));
+        assert_eq!(c.validate("é", false), Err(Violation::TooShort(2)));
+        assert_eq!(c.validate("éèà", false), Ok(()));
+        assert_eq!(c.validate("abcde", false), Err(Violation::TooLong(4)));
+        let c = constraints("min: 1\nmax: 10");
+        assert_eq!(c.validate("n/a", false), Err(Violation::NotANumber));
+        assert_eq!(c.validate("0.5", false), Err(Violation::TooSmall(1.0)));
+        assert_eq!(c.validate("10", false), Ok(()));
+        assert_eq!(c.validate("11", false), Err(Violation::TooLarge(10.0)));
+        let c = constraints("enum: [dev, prod]");
+        assert_eq!(c.validate("prod", false), Ok(()));
+        assert!(matches!(
+            c.validate("test", false),
+            Err(Violation::Choice(_))
+        ));
+        assert!(Constraints::from_meta(&VariableMeta {
+            pattern: Some("(".to_string()),
+            ..Default::default()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn validate_formats() {
+        let c = constraints("format: email");
+        assert_eq!(c.validate("jane@example.com", false), Ok(()));
+        for text in ["jane", "jane@example", "@example.com", "ja ne@example.com"] {
+            assert_eq!(c.validate(text, false), Err(Violation::Email), "{}", text);
+        }
+        let c = constraints("format: url");
+        assert_eq!(c.validate("https://example.com/a?b=c", false), Ok(()));
+        for text in ["example.com", "https://", "1http://example.com"] {
+            assert_eq!(c.validate(text, false), Err(Violation::Url), "{}", text);
+        }
+        let c = constraints("format: date");
+        assert_eq!(c.validate("2024-02-29", false), Ok(()));
+        for text in [
+            "2023-02-29",
+            "2024-13-01",
+            "2024-1-01",
+            "2024-+2-01",
+            "01/02/2024",
+        ] {
+            assert_eq!(c.validate(text, false), Err(Violation::Date), "{}", text);
+        }
+    }
+
+    #[test]
+    fn validate_data_paths() {
+        let constraints = BTreeMap::from([
+            ("title".to_string(), constraints("required: true")),
+            ("authors.email".to_string(), constraints("format: email")),
+        ]);
+        let data = json!({
+            "authors": [{"email": "jane@example.com"}, {"email": "john"}, {}]
+        });
+        let violations = validate_data(&constraints, &data);
+        assert_eq!(
+            violations,
+            vec![
+                ("authors.1.email".to_string(), Violation::Email),
+                ("title".to_string(), Violation::Required),
+            ]
+        );
+    }
+}
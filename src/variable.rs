@@ -6,13 +6,20 @@
 // SPDX-License-Identifier: MIT
 //
 
-use gtk::{glib, glib::Properties, prelude::*, subclass::prelude::*};
-use std::cell::RefCell;
+use crate::data;
+use crate::document::VariableKind;
+use crate::session::SessionValue;
+use crate::validation::Constraints;
+use gtk::{gio, glib, glib::Properties, prelude::*, subclass::prelude::*};
+use serde_json::{Map, Value};
+use std::cell::{Cell, RefCell};
+use std::sync::OnceLock;
 
 #[derive(Default)]
 pub struct VariableData {
     pub name: String,
-    pub value: String,
+    pub path: String,
+    pub value: Value,
 }
 
 mod imp {
@@ -22,8 +29,64 @@ mod imp {
     #[properties(wrapper_type = super::Variable)]
     pub struct Variable {
         #[property(name = "name", get, set, type = String, member = name)]
-        #[property(name = "value", get, set, type = String, member = value)]
+        #[property(name = "path", get, set, type = String, member = path)]
+        #[property(name = "value", get = Self::text, set = Self::set_text, type = String)]
         pub(crate) data: RefCell<VariableData>,
+        #[property(get, set, nullable)]
+        pub(crate) children: RefCell<Option<gio::ListStore>>,
+        #[property(get, set)]
+        pub(crate) missing: Cell<bool>,
+        #[property(get, set)]
+        pub(crate) required: Cell<bool>,
+        #[property(get)]
+        pub(crate) unset: Cell<bool>,
+        #[property(get, set, nullable)]
+        pub(crate) label: RefCell<Option<String>>,
+        #[property(get, set, nullable)]
+        pub(crate) description: RefCell<Option<String>>,
+        #[property(get, set)]
+        pub(crate) multiline: Cell<bool>,
+        #[property(get, nullable)]
+        pub(crate) error: RefCell<Option<String>>,
+        #[property(get)]
+        pub(crate) shared: Cell<bool>,
+        #[property(get, set)]
+        pub(crate) linked: Cell<bool>,
+        pub(crate) session_value: RefCell<Option<SessionValue>>,
+        pub(crate) link: RefCell<Option<glib::Binding>>,
+        pub(crate) mandatory: Cell<bool>,
+        pub(crate) constraints: RefCell<Constraints>,
+        pub(crate) choices: RefCell<Vec<Value>>,
+        pub(crate) index: Cell<u32>,
+        pub(crate) kind: Cell<VariableKind>,
+        pub(crate) parent: glib::WeakRef<super::Variable>,
+        pub(crate) prototype: RefCell<Option<super::Variable>>,
+    }
+
+    impl Variable {
+        /// Return the value as text, as edited in the views.
+        fn text(&self) -> String {
+            data::to_text(&self.data.borrow().value)
+        }
+
+        /// Set the value from its text, according to the kind of the variable.
+        ///
+        /// The text of a choice gives the value declared for it, e.g. a number.
+        fn set_text(&self, text: String) {
+            let kind = self.kind.get();
+            let choice = self
+                .choices
+                .borrow()
+                .iter()
+                .find(|choice| data::to_text(choice) == text)
+                .cloned();
+            if let Some(choice) = choice {
+                self.data.borrow_mut().value = choice;
+                return;
+            }
+            // A number stays unset until one is entered.
+            self.data.borrow_mut().value = kind.parse(&text);
+        }
     }
 
     #[glib::object_subclass]
@@ -33,7 +96,25 @@ mod imp {
     }
 
     #[glib::derived_properties]
-    impl ObjectImpl for Variable {}
+    impl ObjectImpl for Variable {
+        fn signals() -> &'static [glib::subclass::Signal] {
+            static SIGNALS: OnceLock<Vec<glib::subclass::Signal>> = OnceLock::new();
+            SIGNALS.get_or_init(|| vec![glib::subclass::Signal::builder("changed").build()])
+        }
+
+        fn notify(&self, pspec: &glib::ParamSpec) {
+            self.parent_notify(pspec);
+            match pspec.name() {
+                "value" => {
+                    self.obj().validate();
+                    self.obj().emit_changed();
+                }
+                "required" => self.obj().validate(),
+                "linked" => self.obj().update_link(),
+                _ => {}
+            }
+        }
+    }
 }
 
 glib::wrapper! {
@@ -41,16 +122,297 @@ glib::wrapper! {
 }
 
 impl Variable {
-    pub fn new(name: &str, value: &str) -> Self {
-        glib::Object::builder()
+    pub fn new(name: &str, path: &str, kind: VariableKind, value: &str) -> Self {
+        let variable: Self = glib::Object::builder()
+            .property("name", name)
+            .property("path", path)
+            .build();
+        variable.imp().kind.set(kind);
+        variable.set_value(value);
+        variable
+    }
+
+    pub fn with_children(name: &str, path: &str, children: &gio::ListStore) -> Self {
+        let variable: Self = glib::Object::builder()
             .property("name", name)
-            .property("value", value)
-            .build()
+            .property("path", path)
+            .property("children", children)
+            .build();
+        variable.imp().kind.set(VariableKind::Object);
+        for child in variable.items() {
+            child.imp().parent.set(Some(&variable));
+        }
+        variable
+    }
+
+    /// Create a list variable, whose items are copies of `prototype`.
+    pub fn with_prototype(name: &str, path: &str, prototype: &Variable) -> Self {
+        let children = gio::ListStore::new::<Variable>();
+        let variable: Self = glib::Object::builder()
+            .property("name", name)
+            .property("path", path)
+            .property("children", children)
+            .build();
+        variable.imp().kind.set(VariableKind::List);
+        variable.imp().prototype.replace(Some(prototype.clone()));
+        variable
+    }
+
+    /// Create a choice variable, set to its first allowed value.
+    pub fn with_choices(name: &str, path: &str, choices: &[Value]) -> Self {
+        let variable = Self::new(name, path, VariableKind::Choice, "");
+        variable.imp().choices.replace(choices.to_vec());
+        variable.set_value(choices.first().map(data::to_text).unwrap_or_default());
+        variable
+    }
+
+    /// Return the kind of the variable.
+    pub fn kind(&self) -> VariableKind {
+        self.imp().kind.get()
+    }
+
+    /// Return the values allowed for a choice variable.
+    pub fn choices(&self) -> Vec<Value> {
+        self.imp().choices.borrow().clone()
+    }
+
+    /// Return the object or list holding this variable, if any.
+    pub fn parent(&self) -> Option<Variable> {
+        self.imp().parent.upgrade()
+    }
+
+    /// Return `true` if the variable is an item of a list.
+    pub fn is_item(&self) -> bool {
+        self.parent()
+            .is_some_and(|parent| parent.kind() == VariableKind::List)
+    }
+
+    /// Emit the `changed` signal, for this variable and the ones holding it.
+    fn emit_changed(&self) {
+        self.emit_by_name::<()>("changed", &[]);
+        if let Some(parent) = self.parent() {
+            parent.emit_changed();
+        }
+    }
+
+    /// Check the value, flagging the variable as unset or invalid.
+    ///
+    /// Boolean values are always set.
+    fn validate(&self) {
+        let kind = self.kind();
+        let checked = kind.is_scalar() && kind != VariableKind::Boolean;
+        let value = self.value();
+        let unset = checked && self.required() && value.is_empty();
+        if self.imp().unset.replace(unset) != unset {
+            self.notify_unset();
+        }
+        let error = checked
+            .then(|| {
+                self.imp()
+                    .constraints
+                    .borrow()
+                    .validate(&value, self.required())
+            })
+            .and_then(Result::err)
+            .map(|violation| violation.to_string());
+        if *self.imp().error.borrow() != error {
+            self.imp().error.replace(error);
+            self.notify_error();
+        }
+    }
+
+    /// Share the value of the variable with the other variables linked to `session_value`.
+    ///
+    /// The value is linked while the `linked` property is set, starting with `linked`.
+    pub fn share(&self, session_value: &SessionValue, linked: bool) {
+        self.imp()
+            .session_value
+            .replace(Some(session_value.clone()));
+        if !self.imp().shared.replace(true) {
+            self.notify_shared();
+        }
+        self.set_linked(linked);
+    }
+
+    /// Bind the value to the shared one if linked, adopting it unless it is empty.
+    fn update_link(&self) {
+        if let Some(binding) = self.imp().link.take() {
+            binding.unbind();
+        }
+        if !self.linked() {
+            return;
+        }
+        let Some(session_value) = self.imp().session_value.borrow().clone() else {
+            return;
+        };
+        if session_value.value().is_empty() {
+            session_value.set_value(self.value());
+        }
+        let binding = session_value
+            .bind_property("value", self, "value")
+            .bidirectional()
+            .sync_create()
+            .build();
+        self.imp().link.replace(Some(binding));
+    }
+
+    /// Set the constraints on the value of the variable.
+    pub fn set_constraints(&self, constraints: Constraints) {
+        self.imp().constraints.replace(constraints);
+        self.validate();
+    }
+
+    /// Always require a value for the variable, as declared by the template.
+    pub fn set_mandatory(&self, mandatory: bool) {
+        self.imp().mandatory.set(mandatory);
+        self.set_required(self.required() || mandatory);
+    }
+
+    /// Require a value for the variable, its fields and its future items.
+    ///
+    /// Mandatory variables keep requiring a value.
+    pub fn require_values(&self, required: bool) {
+        self.set_required(required || self.imp().mandatory.get());
+        if let Some(prototype) = self.imp().prototype.borrow().as_ref() {
+            prototype.require_values(required);
+        }
+        for child in self.items() {
+            child.require_values(required);
+        }
+    }
+
+    /// Return `true` if neither the variable nor its fields are unset or invalid.
+    pub fn is_valid(&self) -> bool {
+        !self.unset() && self.error().is_none() && self.items().iter().all(Variable::is_valid)
+    }
+
+    /// Return a copy of the variable and of its fields.
+    fn duplicate(&self) -> Variable {
+        let copy = match self.kind() {
+            VariableKind::Object => {
+                let children = gio::ListStore::new::<Variable>();
+                for child in self.items() {
+                    children.append(&child.duplicate());
+                }
+                Variable::with_children(&self.name(), &self.path(), &children)
+            }
+            VariableKind::List => {
+                let prototype = self.imp().prototype.borrow();
+                let prototype = prototype.as_ref().expect("List must have a prototype");
+                Variable::with_prototype(&self.name(), &self.path(), prototype)
+            }
+            kind => {
+                let copy = Variable::new(&self.name(), &self.path(), kind, &self.value());
+                copy.imp().choices.replace(self.choices());
+                copy
+            }
+        };
+        copy.imp().mandatory.set(self.imp().mandatory.get());
+        copy.set_constraints(self.imp().constraints.borrow().clone());
+        copy.set_required(self.required());
+        copy.set_label(self.label());
+        copy.set_description(self.description());
+        copy.set_multiline(self.multiline());
+        copy
+    }
+
+    /// Return the fields or the items of the variable.
+    pub fn items(&self) -> Vec<Variable> {
+        self.children()
+            .map(|children| children.iter::<Variable>().filter_map(Result::ok).collect())
+            .unwrap_or_default()
+    }
+
+    /// Return the position of the variable in its list, if it is an item.
+    pub fn index(&self) -> u32 {
+        self.imp().index.get()
+    }
+
+    /// Append a new item to a list variable, returning it.
+    pub fn add_item(&self) -> Option<Variable> {
+        let prototype = self.imp().prototype.borrow().clone()?;
+        let item = prototype.duplicate();
+        item.imp().parent.set(Some(self));
+        let mut items = self.items();
+        items.push(item.clone());
+        self.set_items(items);
+        Some(item)
+    }
+
+    /// Remove all the items of a list variable.
+    pub fn clear_items(&self) {
+        self.set_items(Vec::new());
+    }
+
+    /// Remove `item` from a list variable.
+    pub fn remove_item(&self, item: &Variable) {
+        if self.kind() != VariableKind::List {
+            return;
+        }
+        let mut items = self.items();
+        items.retain(|i| i != item);
+        self.set_items(items);
+    }
+
+    /// Move `item` of a list variable by `offset` positions.
+    pub fn move_item(&self, item: &Variable, offset: i32) {
+        if self.kind() != VariableKind::List {
+            return;
+        }
+        let mut items = self.items();
+        if let Some(position) = items.iter().position(|i| i == item) {
+            let target = position as i64 + offset as i64;
+            if target >= 0 && target < items.len() as i64 {
+                let item = items.remove(position);
+                items.insert(target as usize, item);
+                self.set_items(items);
+            }
+        }
+    }
+
+    /// Replace the items of a list variable, numbering them by position.
+    ///
+    /// All the items are spliced back so that sorted views pick up the new order.
+    fn set_items(&self, items: Vec<Variable>) {
+        for (index, item) in items.iter().enumerate() {
+            item.imp().index.set(index as u32);
+            item.set_name(format!("#{}", index + 1));
+            item.rebase(&format!("{}.{}", self.path(), index));
+        }
+        if let Some(children) = self.children() {
+            children.splice(0, children.n_items(), &items);
+        }
+        self.emit_changed();
+    }
+
+    /// Set the path of the variable to `path`, updating the paths of its fields.
+    fn rebase(&self, path: &str) {
+        self.set_path(path);
+        for (index, child) in self.items().iter().enumerate() {
+            match self.kind() {
+                VariableKind::List => child.rebase(&format!("{}.{}", path, index)),
+                _ => child.rebase(&format!("{}.{}", path, child.name())),
+            }
+        }
+    }
+
+    /// Return the value of the variable as JSON, to render a template.
+    pub fn to_json(&self) -> Value {
+        match self.kind() {
+            VariableKind::Object => Value::Object(Map::from_iter(
+                self.items()
+                    .into_iter()
+                    .map(|child| (child.name(), child.to_json())),
+            )),
+            VariableKind::List => Value::Array(self.items().iter().map(|i| i.to_json()).collect()),
+            _ => self.imp().data.borrow().value.clone(),
+        }
     }
 }
 
 impl From<VariableData> for Variable {
     fn from(value: VariableData) -> Self {
-        Self::new(&value.name, &value.value)
+        let text = data::to_text(&value.value);
+        Self::new(&value.name, &value.path, VariableKind::String, &text)
     }
 }
@@ -6,10 +6,14 @@
 // SPDX-License-Identifier: MIT
 //
 
+mod batch_dialog;
+mod document_page;
 mod variable_name_cell;
 mod variable_value_cell;
 mod variables_view;
 
+pub use batch_dialog::BatchDialog;
+pub use document_page::DocumentPage;
 pub use variable_name_cell::VariableNameCell;
 pub use variable_value_cell::VariableValueCell;
 pub use variables_view::VariablesView;
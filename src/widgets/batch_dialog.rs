@@ -0,0 +1,265 @@
+//
+// This file is part of template-filler
+//
+// SPDX-FileCopyrightText: © 2024 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+use crate::batch::Batch;
+use crate::document::Document;
+use glib::clone;
+use gtk::{gio, glib, prelude::*, subclass::prelude::*};
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+/// Label of the choice mapping a variable to no column.
+const NO_COLUMN: &str = "(None)";
+
+mod imp {
+    use super::*;
+
+    #[derive(Debug, Default, gtk::CompositeTemplate)]
+    #[template(resource = "/com/elebihan/TemplateFiller/ui/batch_dialog.ui")]
+    pub struct BatchDialog {
+        #[template_child]
+        pub(crate) close_button: gtk::TemplateChild<gtk::Button>,
+        #[template_child]
+        pub(crate) render_button: gtk::TemplateChild<gtk::Button>,
+        #[template_child]
+        pub(crate) stack: gtk::TemplateChild<gtk::Stack>,
+        #[template_child]
+        pub(crate) records_label: gtk::TemplateChild<gtk::Label>,
+        #[template_child]
+        pub(crate) mapping_grid: gtk::TemplateChild<gtk::Grid>,
+        #[template_child]
+        pub(crate) output_button: gtk::TemplateChild<gtk::Button>,
+        #[template_child]
+        pub(crate) output_label: gtk::TemplateChild<gtk::Label>,
+        #[template_child]
+        pub(crate) pattern_entry: gtk::TemplateChild<gtk::Entry>,
+        #[template_child]
+        pub(crate) progress_bar: gtk::TemplateChild<gtk::ProgressBar>,
+        #[template_child]
+        pub(crate) report_view: gtk::TemplateChild<gtk::TextView>,
+        pub(crate) document: RefCell<Option<Document>>,
+        pub(crate) batch: RefCell<Option<Batch>>,
+        pub(crate) columns: RefCell<Vec<(String, gtk::DropDown)>>,
+        pub(crate) output_dir: RefCell<PathBuf>,
+        pub(crate) render_source: RefCell<Option<glib::SourceId>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for BatchDialog {
+        const NAME: &'static str = "BatchDialog";
+        type Type = super::BatchDialog;
+        type ParentType = gtk::Window;
+
+        fn class_init(klass: &mut Self::Class) {
+            klass.bind_template();
+        }
+
+        fn instance_init(obj: &glib::subclass::InitializingObject<Self>) {
+            obj.init_template();
+        }
+    }
+
+    impl ObjectImpl for BatchDialog {
+        fn constructed(&self) {
+            self.parent_constructed();
+            let obj = self.obj();
+            self.close_button.connect_clicked(clone!(
+                #[weak]
+                obj,
+                move |_| obj.close()
+            ));
+            self.render_button.connect_clicked(clone!(
+                #[weak]
+                obj,
+                move |_| obj.start_render()
+            ));
+            self.output_button.connect_clicked(clone!(
+                #[weak]
+                obj,
+                move |_| obj.show_output_dialog()
+            ));
+        }
+
+        fn dispose(&self) {
+            if let Some(source) = self.render_source.take() {
+                source.remove();
+            }
+            self.dispose_template();
+        }
+    }
+
+    impl WidgetImpl for BatchDialog {}
+    impl WindowImpl for BatchDialog {}
+}
+
+glib::wrapper! {
+    pub struct BatchDialog(ObjectSubclass<imp::BatchDialog>)
+        @extends gtk::Widget, gtk::Window,
+        @implements gtk::Root;
+}
+
+impl BatchDialog {
+    /// Create a dialog to render `document` once per record of `batch`, into `dir` by default.
+    pub fn new(
+        parent: &impl IsA<gtk::Window>,
+        document: Document,
+        batch: Batch,
+        dir: &Path,
+    ) -> Self {
+        let dialog: Self = glib::Object::builder()
+            .property("transient-for", parent)
+            .build();
+        let imp = dialog.imp();
+        imp.records_label.set_label(&format!(
+            "{} records to render. Choose the column holding the value of each variable, \
+             the others keep the values set in the template.",
+            batch.len()
+        ));
+        imp.pattern_entry.set_text(batch.pattern());
+        dialog.setup_mapping(&batch);
+        dialog.set_output_dir(dir);
+        imp.document.replace(Some(document));
+        imp.batch.replace(Some(batch));
+        dialog
+    }
+
+    /// Add a row per variable, to choose the column holding its value.
+    fn setup_mapping(&self, batch: &Batch) {
+        let imp = self.imp();
+        let columns = batch.columns();
+        let choices: Vec<&str> = std::iter::once(NO_COLUMN)
+            .chain(columns.iter().map(String::as_str))
+            .collect();
+        let mut dropdowns = imp.columns.borrow_mut();
+        for (row, path) in batch.fields().enumerate() {
+            let label = gtk::Label::builder().label(path).xalign(0.0).build();
+            let dropdown = gtk::DropDown::from_strings(&choices);
+            dropdown.set_hexpand(true);
+            let selected = batch
+                .column(path)
+                .and_then(|column| columns.iter().position(|c| c == column))
+                .map_or(0, |index| index + 1);
+            dropdown.set_selected(selected as u32);
+            imp.mapping_grid.attach(&label, 0, row as i32, 1, 1);
+            imp.mapping_grid.attach(&dropdown, 1, row as i32, 1, 1);
+            dropdowns.push((path.to_string(), dropdown));
+        }
+    }
+
+    fn set_output_dir(&self, dir: &Path) {
+        self.imp()
+            .output_label
+            .set_label(&dir.display().to_string());
+        self.imp().output_dir.replace(dir.into());
+    }
+
+    fn show_output_dialog(&self) {
+        let dialog = gtk::FileChooserDialog::builder()
+            .title("Select output directory")
+            .action(gtk::FileChooserAction::SelectFolder)
+            .transient_for(self)
+            .modal(true)
+            .build();
+        let folder = gio::File::for_path(self.imp().output_dir.borrow().as_path());
+        if let Err(error) = dialog.set_current_folder(Some(&folder)) {
+            debug!("Failed to browse {}: {}", folder.uri(), error);
+        }
+        dialog.add_buttons(&[
+            ("_Cancel", gtk::ResponseType::Cancel),
+            ("_Select", gtk::ResponseType::Accept),
+        ]);
+        dialog.connect_response(clone!(
+            #[weak(rename_to = batch_dialog)]
+            self,
+            move |dialog, response| {
+                dialog.close();
+                if response == gtk::ResponseType::Accept {
+                    if let Some(path) = dialog.file().and_then(|file| file.path()) {
+                        batch_dialog.set_output_dir(&path);
+                    }
+                }
+            }
+        ));
+        dialog.show();
+    }
+
+    /// Render the records one at a time, reporting the progress and the failures.
+    fn start_render(&self) {
+        let imp = self.imp();
+        let (Some(mut batch), Some(document)) = (imp.batch.take(), imp.document.take()) else {
+            return;
+        };
+        for (path, dropdown) in imp.columns.borrow().iter() {
+            let column = dropdown
+                .selected_item()
+                .and_downcast::<gtk::StringObject>()
+                .filter(|_| dropdown.selected() != 0)
+                .map(|item| item.string());
+            batch.set_column(path, column.as_deref());
+        }
+        batch.set_pattern(&imp.pattern_entry.text());
+        let total = batch.len();
+        let dir = imp.output_dir.borrow().clone();
+        debug!("Rendering {} records into {}", total, dir.display());
+        imp.stack.set_visible_child_name("progress");
+        imp.render_button.set_sensitive(false);
+        self.set_progress(0, total);
+        let mut rendering = batch.render(document, &dir);
+        let mut failed = 0;
+        let source = glib::idle_add_local(clone!(
+            #[weak(rename_to = dialog)]
+            self,
+            #[upgrade_or]
+            glib::ControlFlow::Break,
+            move || match rendering.next() {
+                Some((index, result)) => {
+                    if let Err(error) = result {
+                        dialog.report(&format!("Record {}: {}", index + 1, error));
+                        failed += 1;
+                    }
+                    dialog.set_progress(index + 1, total);
+                    glib::ControlFlow::Continue
+                }
+                None => {
+                    dialog.finish(total - failed, total, &dir);
+                    glib::ControlFlow::Break
+                }
+            }
+        ));
+        imp.render_source.replace(Some(source));
+    }
+
+    fn set_progress(&self, done: usize, total: usize) {
+        let progress_bar = self.imp().progress_bar.get();
+        if total > 0 {
+            progress_bar.set_fraction(done as f64 / total as f64);
+        }
+        progress_bar.set_text(Some(&format!("{} / {}", done, total)));
+    }
+
+    /// Append a line to the report of the rendering.
+    fn report(&self, text: &str) {
+        let buffer = self.imp().report_view.buffer();
+        buffer.insert(&mut buffer.end_iter(), &format!("{}\n", text));
+    }
+
+    fn finish(&self, rendered: usize, total: usize, dir: &Path) {
+        // The source is removed when the callback returns.
+        self.imp().render_source.take();
+        self.report(&format!(
+            "Rendered {} of {} records into {}",
+            rendered,
+            total,
+            dir.display()
+        ));
+        let close_button = self.imp().close_button.get();
+        close_button.set_use_underline(true);
+        close_button.set_label("_Close");
+    }
+}
@@ -0,0 +1,509 @@
+//
+// This file is part of template-filler
+//
+// SPDX-FileCopyrightText: © 2024 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+use crate::data;
+use crate::document::{self, Document, VariableKind, VariableNode};
+use crate::profiles::Profiles;
+use crate::session::Session;
+use crate::variable::Variable;
+use crate::widgets::{VariableNameCell, VariableValueCell, VariablesView};
+use glib::clone;
+use gtk::{gio, glib, glib::Properties, prelude::*, subclass::prelude::*};
+use serde_json::Value;
+use std::cell::{Cell, RefCell};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Delay after the last change of a value before refreshing the preview.
+const PREVIEW_DELAY: Duration = Duration::from_millis(300);
+
+mod imp {
+    use super::*;
+
+    #[derive(Debug, Default, Properties, gtk::CompositeTemplate)]
+    #[properties(wrapper_type = super::DocumentPage)]
+    #[template(resource = "/com/elebihan/TemplateFiller/ui/document_page.ui")]
+    pub struct DocumentPage {
+        #[template_child]
+        pub(crate) variables_view: gtk::TemplateChild<VariablesView>,
+        #[template_child]
+        pub(crate) preview_view: gtk::TemplateChild<gtk::TextView>,
+        pub(crate) document: RefCell<Option<Document>>,
+        pub(crate) variables: RefCell<Option<gio::ListStore>>,
+        pub(crate) preview_source: RefCell<Option<glib::SourceId>>,
+        #[property(get, set)]
+        pub(crate) modified: Cell<bool>,
+        #[property(get)]
+        pub(crate) complete: Cell<bool>,
+        #[property(get, set, nullable)]
+        pub(crate) profile: RefCell<Option<String>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for DocumentPage {
+        const NAME: &'static str = "DocumentPage";
+        type Type = super::DocumentPage;
+        type ParentType = gtk::Box;
+
+        fn class_init(klass: &mut Self::Class) {
+            klass.bind_template();
+        }
+
+        fn instance_init(obj: &glib::subclass::InitializingObject<Self>) {
+            obj.init_template();
+        }
+    }
+
+    #[glib::derived_properties]
+    impl ObjectImpl for DocumentPage {
+        fn constructed(&self) {
+            self.parent_constructed();
+            self.obj().setup_variables();
+            self.obj().setup_factories();
+        }
+
+        fn dispose(&self) {
+            if let Some(source) = self.preview_source.take() {
+                source.remove();
+            }
+            self.dispose_template();
+        }
+    }
+
+    impl WidgetImpl for DocumentPage {}
+    impl BoxImpl for DocumentPage {}
+}
+
+glib::wrapper! {
+    pub struct DocumentPage(ObjectSubclass<imp::DocumentPage>)
+        @extends gtk::Widget, gtk::Box;
+}
+
+impl DocumentPage {
+    /// Create a page to fill the variables of `document`.
+    pub fn new(document: Document, strict: bool) -> Self {
+        let page: Self = glib::Object::new();
+        page.load_variables(&document);
+        page.imp().document.replace(Some(document));
+        page.set_strict(strict);
+        page.schedule_preview();
+        page
+    }
+
+    /// Return the path of the template, or directory of templates.
+    pub fn path(&self) -> PathBuf {
+        self.imp()
+            .document
+            .borrow()
+            .as_ref()
+            .map(|document| document.path().to_path_buf())
+            .unwrap_or_default()
+    }
+
+    /// Return the name of the template, to show to the user.
+    pub fn title(&self) -> String {
+        self.path()
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+
+    /// Return `true` if the document is a directory of templates.
+    pub fn is_directory(&self) -> bool {
+        self.imp()
+            .document
+            .borrow()
+            .as_ref()
+            .is_some_and(Document::is_directory)
+    }
+
+    /// Return a copy of the document, to render it apart from the page.
+    pub fn document(&self) -> Option<Document> {
+        self.imp().document.borrow().clone()
+    }
+
+    /// Return the profiles of the template.
+    pub fn profiles(&self) -> Profiles {
+        Profiles::new(self.path())
+    }
+
+    /// Set the values of the variables from `data`.
+    ///
+    /// Return the dotted paths of the values not used by the template.
+    pub fn load_values(&self, data: &Value) -> Vec<String> {
+        for variable in self.variables() {
+            Self::apply_value(&variable, data.get(variable.name()));
+        }
+        self.imp()
+            .document
+            .borrow()
+            .as_ref()
+            .map(|document| data::unknown_paths(document.tree(), data))
+            .unwrap_or_default()
+    }
+
+    /// Set the values of the variables found in `data`, leaving the others untouched.
+    pub fn restore_values(&self, data: &Value) {
+        for variable in self.variables() {
+            if let Some(value) = data.get(variable.name()) {
+                Self::apply_value(&variable, Some(value));
+            }
+        }
+    }
+
+    /// Share the values of the variables with the ones with the same path and kind in `session`.
+    ///
+    /// If `linked` is set, the variables take the shared values, otherwise they
+    /// keep their own until linked. The items of the lists are not shared.
+    pub fn share_values(&self, session: &Session, linked: bool) {
+        for variable in self.variables() {
+            Self::share_value(&variable, session, linked);
+        }
+    }
+
+    fn share_value(variable: &Variable, session: &Session, linked: bool) {
+        match variable.kind() {
+            VariableKind::Object => {
+                for child in variable.items() {
+                    Self::share_value(&child, session, linked);
+                }
+            }
+            VariableKind::List => {}
+            kind => {
+                let choices = variable.choices();
+                if let Some(session_value) = session.value(&variable.path(), kind, &choices) {
+                    variable.share(&session_value, linked);
+                }
+            }
+        }
+    }
+
+    /// Set the values of the variables found in the environment variables named after
+    /// them with `prefix`, leaving the others untouched.
+    ///
+    /// Nothing is set if a value does not fit the kind of its variable.
+    pub fn import_env(&self, prefix: &str) -> Result<(), document::Error> {
+        let data = self
+            .imp()
+            .document
+            .borrow()
+            .as_ref()
+            .map(|document| data::from_env(document.tree(), prefix, data::env_vars()))
+            .transpose()?;
+        if let Some(data) = data {
+            self.restore_values(&data);
+        }
+        Ok(())
+    }
+
+    /// Set the values of the variables back to their defaults.
+    pub fn reset_values(&self) {
+        if let Some(document) = self.imp().document.borrow().as_ref() {
+            self.load_variables(document);
+        }
+        let strict = self
+            .imp()
+            .document
+            .borrow()
+            .as_ref()
+            .is_some_and(Document::is_strict);
+        self.set_strict(strict);
+        self.schedule_preview();
+    }
+
+    fn apply_value(variable: &Variable, value: Option<&Value>) {
+        match variable.kind() {
+            VariableKind::Object => {
+                for child in variable.items() {
+                    Self::apply_value(&child, value.and_then(|v| v.get(child.name())));
+                }
+            }
+            VariableKind::List => match value {
+                Some(Value::Array(values)) => {
+                    variable.clear_items();
+                    for value in values {
+                        if let Some(item) = variable.add_item() {
+                            Self::apply_value(&item, Some(value));
+                        }
+                    }
+                    variable.set_missing(false);
+                }
+                _ => variable.set_missing(true),
+            },
+            _ => match value {
+                Some(value) => {
+                    variable.set_value(data::to_text(value));
+                    variable.set_missing(false);
+                }
+                None => variable.set_missing(true),
+            },
+        }
+    }
+
+    fn load_variables(&self, document: &Document) {
+        let variables = self.imp().variables.borrow();
+        if let Some(list_store) = variables.as_ref() {
+            list_store.remove_all();
+            for node in document.tree() {
+                let variable = Self::create_variable(document, node);
+                variable.connect_closure(
+                    "changed",
+                    false,
+                    glib::closure_local!(
+                        #[weak(rename_to = page)]
+                        self,
+                        move |_: Variable| {
+                            page.set_modified(true);
+                            page.schedule_preview();
+                            page.update_complete();
+                        }
+                    ),
+                );
+                list_store.append(&variable);
+            }
+        }
+    }
+
+    fn create_variable(document: &Document, node: &VariableNode) -> Variable {
+        let variable = match node.kind() {
+            VariableKind::Object => {
+                let children = gio::ListStore::new::<Variable>();
+                for child in node.children() {
+                    children.append(&Self::create_variable(document, child));
+                }
+                Variable::with_children(node.name(), node.path(), &children)
+            }
+            VariableKind::List => {
+                let prototype = if node.is_leaf() {
+                    Variable::new("", node.path(), VariableKind::String, "")
+                } else {
+                    let children = gio::ListStore::new::<Variable>();
+                    for child in node.children() {
+                        children.append(&Self::create_variable(document, child));
+                    }
+                    Variable::with_children("", node.path(), &children)
+                };
+                Variable::with_prototype(node.name(), node.path(), &prototype)
+            }
+            VariableKind::Choice => {
+                Variable::with_choices(node.name(), node.path(), node.choices())
+            }
+            kind => Variable::new(node.name(), node.path(), kind, ""),
+        };
+        if let Some(metadata) = document.metadata(node.path()) {
+            variable.set_label(metadata.label.as_deref());
+            variable.set_description(metadata.description.as_deref());
+            variable.set_mandatory(metadata.required);
+            variable.set_multiline(metadata.multiline);
+            if let Some(constraints) = document.constraints(node.path()) {
+                variable.set_constraints(constraints.clone());
+            }
+            if metadata.default.is_some() {
+                Self::apply_value(&variable, metadata.default.as_ref());
+            }
+        }
+        variable
+    }
+
+    /// Return the top-level variables.
+    fn variables(&self) -> Vec<Variable> {
+        self.imp()
+            .variables
+            .borrow()
+            .iter()
+            .flat_map(|list_store| list_store.iter::<Variable>())
+            .filter_map(Result::ok)
+            .collect()
+    }
+
+    /// Enable or disable strict rendering, requiring a value for every variable.
+    pub fn set_strict(&self, strict: bool) {
+        if let Some(document) = self.imp().document.borrow_mut().as_mut() {
+            document.set_strict(strict);
+        }
+        for variable in self.variables() {
+            variable.require_values(strict);
+        }
+        self.update_complete();
+    }
+
+    /// Flag the page as complete if every value is set and valid.
+    fn update_complete(&self) {
+        let complete = self.variables().iter().all(Variable::is_valid);
+        if self.imp().complete.replace(complete) != complete {
+            self.notify_complete();
+        }
+    }
+
+    /// Render the template into `path` with the values of the variables.
+    pub fn render(&self, path: &Path) -> Result<(), document::Error> {
+        if let Some(document) = self.imp().document.borrow().as_ref() {
+            let data = self.collect_data();
+            if document.is_directory() {
+                document.render_to_dir(path, &data)?;
+            } else {
+                document.render_to_file(path, &data)?;
+            }
+            self.set_modified(false);
+        }
+        Ok(())
+    }
+
+    /// Save the values of the variables to the data file at `path`.
+    pub fn export_values(&self, path: &Path) -> Result<(), document::Error> {
+        data::save(path, &self.collect_data())
+    }
+
+    /// Refresh the preview once the values have not changed for a while.
+    fn schedule_preview(&self) {
+        if let Some(source) = self.imp().preview_source.take() {
+            source.remove();
+        }
+        let source = glib::timeout_add_local_once(
+            PREVIEW_DELAY,
+            clone!(
+                #[weak(rename_to = page)]
+                self,
+                move || {
+                    page.imp().preview_source.take();
+                    page.update_preview();
+                }
+            ),
+        );
+        self.imp().preview_source.replace(Some(source));
+    }
+
+    fn update_preview(&self) {
+        if let Some(document) = self.imp().document.borrow().as_ref() {
+            let data = self.collect_data();
+            match document.render_to_string(&data) {
+                Ok(output) => self.show_preview(&output, false),
+                Err(error) => self.show_preview(&error.to_string(), true),
+            }
+        }
+    }
+
+    fn show_preview(&self, text: &str, is_error: bool) {
+        let preview_view = self.imp().preview_view.get();
+        preview_view.buffer().set_text(text);
+        if is_error {
+            preview_view.add_css_class("error");
+        } else {
+            preview_view.remove_css_class("error");
+        }
+    }
+
+    /// Collect the values of the variables as the data to render the template.
+    pub fn collect_data(&self) -> Value {
+        document::build_data(
+            self.variables()
+                .iter()
+                .map(|variable| (variable.path(), variable.to_json())),
+        )
+    }
+
+    fn setup_variables(&self) {
+        let model = gio::ListStore::new::<Variable>();
+        let sorter = self
+            .imp()
+            .variables_view
+            .sorter()
+            .expect("VariablesView must have a Sorter");
+        let tree_model = gtk::TreeListModel::new(model.clone(), false, true, |item| {
+            item.downcast_ref::<Variable>()
+                .and_then(|variable| variable.children())
+                .and_upcast::<gio::ListModel>()
+        });
+        let sorter = gtk::TreeListRowSorter::new(Some(sorter));
+        let sorted_model = gtk::SortListModel::new(Some(tree_model), Some(sorter));
+        self.imp().variables.replace(Some(model));
+        let selection_model = gtk::NoSelection::new(Some(sorted_model));
+        self.imp().variables_view.set_model(Some(&selection_model));
+    }
+
+    fn setup_factories(&self) {
+        let factory = gtk::SignalListItemFactory::new();
+        factory.connect_setup(move |_, list_item| {
+            let variable_cell = VariableNameCell::new();
+            list_item
+                .downcast_ref::<gtk::ListItem>()
+                .expect("VariableNameCell must be a ListItem")
+                .set_child(Some(&variable_cell));
+        });
+        factory.connect_bind(move |_, list_item| {
+            let row = list_item
+                .downcast_ref::<gtk::ListItem>()
+                .expect("Needs to be a ListItem")
+                .item()
+                .and_downcast::<gtk::TreeListRow>()
+                .expect("The item must be a TreeListRow");
+            let variable = row
+                .item()
+                .and_downcast::<Variable>()
+                .expect("The item must be a Variable");
+            let cell = list_item
+                .downcast_ref::<gtk::ListItem>()
+                .expect("Needs to be a ListItem")
+                .child()
+                .and_downcast::<VariableNameCell>()
+                .expect("The child must be a VariableNameCell");
+            cell.bind(&row, &variable);
+        });
+        factory.connect_unbind(move |_, list_item| {
+            let cell = list_item
+                .downcast_ref::<gtk::ListItem>()
+                .expect("Needs to be ListItem")
+                .child()
+                .and_downcast::<VariableNameCell>()
+                .expect("The child must be a VariableNameCell");
+            cell.unbind();
+        });
+        self.imp()
+            .variables_view
+            .set_name_column_factory(Some(&factory));
+
+        let factory = gtk::SignalListItemFactory::new();
+        factory.connect_setup(move |_, list_item| {
+            let variable_cell = VariableValueCell::new();
+            list_item
+                .downcast_ref::<gtk::ListItem>()
+                .expect("VariableValueCell must be a ListItem")
+                .set_child(Some(&variable_cell));
+        });
+        factory.connect_bind(move |_, list_item| {
+            let variable = list_item
+                .downcast_ref::<gtk::ListItem>()
+                .expect("Needs to be a ListItem")
+                .item()
+                .and_downcast::<gtk::TreeListRow>()
+                .expect("The item must be a TreeListRow")
+                .item()
+                .and_downcast::<Variable>()
+                .expect("The item must be a Variable");
+            let cell = list_item
+                .downcast_ref::<gtk::ListItem>()
+                .expect("Needs to be a ListItem")
+                .child()
+                .and_downcast::<VariableValueCell>()
+                .expect("The child must be a VariableValueCell");
+            cell.bind(&variable);
+        });
+        factory.connect_unbind(move |_, list_item| {
+            let cell = list_item
+                .downcast_ref::<gtk::ListItem>()
+                .expect("Needs to be ListItem")
+                .child()
+                .and_downcast::<VariableValueCell>()
+                .expect("The child must be a VariableValueCell");
+            cell.unbind();
+        });
+        self.imp()
+            .variables_view
+            .set_value_column_factory(Some(&factory));
+    }
+}
@@ -19,8 +19,12 @@ mod imp {
     #[derive(Default, gtk::CompositeTemplate)]
     #[template(resource = "/com/elebihan/TemplateFiller/ui/variable_name_cell.ui")]
     pub struct VariableNameCell {
+        #[template_child]
+        pub expander: gtk::TemplateChild<gtk::TreeExpander>,
         #[template_child]
         pub name_inscription: gtk::TemplateChild<gtk::Inscription>,
+        #[template_child]
+        pub link_button: gtk::TemplateChild<gtk::ToggleButton>,
         pub bindings: RefCell<Vec<glib::Binding>>,
     }
 
@@ -62,19 +66,60 @@ impl VariableNameCell {
         glib::Object::builder().build()
     }
 
-    pub fn bind(&self, variable: &Variable) {
+    pub fn bind(&self, row: &gtk::TreeListRow, variable: &Variable) {
+        self.imp().expander.set_list_row(Some(row));
         let mut bindings = self.imp().bindings.borrow_mut();
 
         let name_inscription = self.imp().name_inscription.get();
+        let name_property = if variable.label().is_some() {
+            "label"
+        } else {
+            "name"
+        };
         let name_inscription_binding = variable
-            .bind_property("name", &name_inscription, "text")
-            .bidirectional()
+            .bind_property(name_property, &name_inscription, "text")
             .sync_create()
             .build();
         bindings.push(name_inscription_binding);
+        let description_binding = variable
+            .bind_property("description", &self.imp().expander.get(), "tooltip-text")
+            .sync_create()
+            .build();
+        bindings.push(description_binding);
+        let missing_binding = variable
+            .bind_property("missing", &name_inscription, "css-classes")
+            .transform_to(|_, missing: bool| {
+                let classes = if missing { vec!["warning"] } else { vec![] };
+                Some(glib::StrV::from(classes))
+            })
+            .sync_create()
+            .build();
+        bindings.push(missing_binding);
+        let unset_binding = variable
+            .bind_property("unset", &self.imp().expander.get(), "css-classes")
+            .transform_to(|_, unset: bool| {
+                let classes = if unset { vec!["error"] } else { vec![] };
+                Some(glib::StrV::from(classes))
+            })
+            .sync_create()
+            .build();
+        bindings.push(unset_binding);
+        let link_button = self.imp().link_button.get();
+        let shared_binding = variable
+            .bind_property("shared", &link_button, "visible")
+            .sync_create()
+            .build();
+        bindings.push(shared_binding);
+        let linked_binding = variable
+            .bind_property("linked", &link_button, "active")
+            .bidirectional()
+            .sync_create()
+            .build();
+        bindings.push(linked_binding);
     }
 
     pub fn unbind(&self) {
+        self.imp().expander.set_list_row(None);
         for binding in self.imp().bindings.borrow_mut().drain(..) {
             binding.unbind();
         }
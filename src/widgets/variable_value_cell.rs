@@ -6,9 +6,13 @@
 // SPDX-License-Identifier: MIT
 //
 
+use crate::data;
+use crate::document::VariableKind;
 use crate::variable::Variable;
+use glib::clone;
 use gtk::{
     glib::{self, object::*},
+    prelude::*,
     subclass::prelude::*,
 };
 use std::cell::RefCell;
@@ -21,7 +25,31 @@ mod imp {
     pub struct VariableValueCell {
         #[template_child]
         pub value_label: gtk::TemplateChild<gtk::EditableLabel>,
+        #[template_child]
+        pub value_preview: gtk::TemplateChild<gtk::Label>,
+        #[template_child]
+        pub text_button: gtk::TemplateChild<gtk::MenuButton>,
+        #[template_child]
+        pub value_text: gtk::TemplateChild<gtk::TextView>,
+        #[template_child]
+        pub value_switch: gtk::TemplateChild<gtk::Switch>,
+        #[template_child]
+        pub value_spin: gtk::TemplateChild<gtk::SpinButton>,
+        #[template_child]
+        pub value_dropdown: gtk::TemplateChild<gtk::DropDown>,
+        #[template_child]
+        pub add_button: gtk::TemplateChild<gtk::Button>,
+        #[template_child]
+        pub item_box: gtk::TemplateChild<gtk::Box>,
+        #[template_child]
+        pub up_button: gtk::TemplateChild<gtk::Button>,
+        #[template_child]
+        pub down_button: gtk::TemplateChild<gtk::Button>,
+        #[template_child]
+        pub remove_button: gtk::TemplateChild<gtk::Button>,
         pub bindings: RefCell<Vec<glib::Binding>>,
+        pub variable: RefCell<Option<Variable>>,
+        pub error_handler: RefCell<Option<(Variable, glib::SignalHandlerId)>>,
     }
 
     #[glib::object_subclass]
@@ -43,6 +71,8 @@ mod imp {
     impl ObjectImpl for VariableValueCell {
         fn constructed(&self) {
             self.parent_constructed();
+            self.obj().setup_buttons();
+            self.obj().setup_spin();
         }
 
         fn dispose(&self) {
@@ -62,25 +92,244 @@ impl VariableValueCell {
         glib::Object::builder().build()
     }
 
+    /// Leave the spin button empty while the number is unset, until one is entered.
+    fn setup_spin(&self) {
+        let imp = self.imp();
+        imp.value_spin.connect_output(clone!(
+            #[weak(rename_to = cell)]
+            self,
+            #[upgrade_or]
+            glib::Propagation::Proceed,
+            move |spin| {
+                if cell.is_unset_number() {
+                    spin.set_text("");
+                    return glib::Propagation::Stop;
+                }
+                glib::Propagation::Proceed
+            }
+        ));
+        // Entering the value already shown by the spin button does not change it.
+        imp.value_spin.connect_input(clone!(
+            #[weak(rename_to = cell)]
+            self,
+            #[upgrade_or]
+            None,
+            move |spin| {
+                let text = spin.text().trim().to_string();
+                if cell.is_unset_number() && text.parse::<f64>().is_ok() {
+                    let variable = cell.imp().variable.borrow().clone();
+                    glib::idle_add_local_once(move || {
+                        if let Some(variable) = variable {
+                            variable.set_value(text);
+                        }
+                    });
+                }
+                None
+            }
+        ));
+    }
+
+    /// Return `true` if the cell shows a number without value.
+    fn is_unset_number(&self) -> bool {
+        self.imp()
+            .variable
+            .borrow()
+            .as_ref()
+            .is_some_and(|v| v.kind() == VariableKind::Number && v.value().is_empty())
+    }
+
+    fn setup_buttons(&self) {
+        let imp = self.imp();
+        imp.add_button.connect_clicked(clone!(
+            #[weak(rename_to = cell)]
+            self,
+            move |_| {
+                let variable = cell.imp().variable.borrow().clone();
+                if let Some(variable) = variable {
+                    variable.add_item();
+                }
+            }
+        ));
+        imp.up_button.connect_clicked(clone!(
+            #[weak(rename_to = cell)]
+            self,
+            move |_| cell.move_item(-1)
+        ));
+        imp.down_button.connect_clicked(clone!(
+            #[weak(rename_to = cell)]
+            self,
+            move |_| cell.move_item(1)
+        ));
+        imp.remove_button.connect_clicked(clone!(
+            #[weak(rename_to = cell)]
+            self,
+            move |_| {
+                let variable = cell.imp().variable.borrow().clone();
+                if let Some(variable) = variable {
+                    if let Some(parent) = variable.parent() {
+                        parent.remove_item(&variable);
+                    }
+                }
+            }
+        ));
+    }
+
+    fn move_item(&self, offset: i32) {
+        let variable = self.imp().variable.borrow().clone();
+        if let Some(variable) = variable {
+            if let Some(parent) = variable.parent() {
+                parent.move_item(&variable, offset);
+            }
+        }
+    }
+
     pub fn bind(&self, variable: &Variable) {
+        self.imp().variable.replace(Some(variable.clone()));
+        self.imp()
+            .add_button
+            .set_visible(variable.kind() == VariableKind::List);
+        self.imp().item_box.set_visible(variable.is_item());
         let mut bindings = self.imp().bindings.borrow_mut();
-
-        let value_label = self.imp().value_label.get();
-        let value_label_binding = variable
-            .bind_property("value", &value_label, "text")
-            .bidirectional()
+        let error_binding = variable
+            .bind_property("error", self, "tooltip-text")
             .sync_create()
             .build();
-        bindings.push(value_label_binding);
+        bindings.push(error_binding);
+        self.update_error_class(variable);
+        let handler = variable.connect_error_notify(clone!(
+            #[weak(rename_to = cell)]
+            self,
+            move |variable| cell.update_error_class(variable)
+        ));
+        let previous = self
+            .imp()
+            .error_handler
+            .replace(Some((variable.clone(), handler)));
+        if let Some((variable, handler)) = previous {
+            variable.disconnect(handler);
+        }
+
+        let kind = variable.kind();
+        let value_label = self.imp().value_label.get();
+        let value_preview = self.imp().value_preview.get();
+        value_label.set_visible(false);
+        value_preview.set_visible(false);
+        self.imp()
+            .text_button
+            .set_visible(kind == VariableKind::String);
+        let value_switch = self.imp().value_switch.get();
+        value_switch.set_visible(kind == VariableKind::Boolean);
+        let value_spin = self.imp().value_spin.get();
+        value_spin.set_visible(kind == VariableKind::Number);
+        let value_dropdown = self.imp().value_dropdown.get();
+        value_dropdown.set_visible(kind == VariableKind::Choice);
+        match kind {
+            VariableKind::String => {
+                let value_label_binding = variable
+                    .bind_property("value", &value_label, "text")
+                    .bidirectional()
+                    .sync_create()
+                    .build();
+                bindings.push(value_label_binding);
+                // Values on several lines can only be edited in the popover.
+                let multiline = variable.multiline();
+                let value_label_visible_binding = variable
+                    .bind_property("value", &value_label, "visible")
+                    .transform_to(move |_, text: String| Some(!multiline && !text.contains('\n')))
+                    .sync_create()
+                    .build();
+                bindings.push(value_label_visible_binding);
+                let value_preview_visible_binding = variable
+                    .bind_property("value", &value_preview, "visible")
+                    .transform_to(move |_, text: String| Some(multiline || text.contains('\n')))
+                    .sync_create()
+                    .build();
+                bindings.push(value_preview_visible_binding);
+                let value_preview_binding = variable
+                    .bind_property("value", &value_preview, "label")
+                    .transform_to(|_, text: String| Some(preview(&text)))
+                    .sync_create()
+                    .build();
+                bindings.push(value_preview_binding);
+                let value_text_binding = variable
+                    .bind_property("value", &self.imp().value_text.buffer(), "text")
+                    .bidirectional()
+                    .sync_create()
+                    .build();
+                bindings.push(value_text_binding);
+            }
+            VariableKind::Boolean => {
+                let value_switch_binding = variable
+                    .bind_property("value", &value_switch, "active")
+                    .transform_to(|_, text: String| Some(text == "true"))
+                    .transform_from(|_, active: bool| Some(active.to_string()))
+                    .bidirectional()
+                    .sync_create()
+                    .build();
+                bindings.push(value_switch_binding);
+            }
+            VariableKind::Number => {
+                let value_spin_binding = variable
+                    .bind_property("value", &value_spin, "value")
+                    .transform_to(|_, text: String| Some(text.parse::<f64>().unwrap_or_default()))
+                    .transform_from(|_, value: f64| Some(value.to_string()))
+                    .bidirectional()
+                    .sync_create()
+                    .build();
+                bindings.push(value_spin_binding);
+            }
+            VariableKind::Choice => {
+                let choices: Vec<String> = variable.choices().iter().map(data::to_text).collect();
+                let strings: Vec<&str> = choices.iter().map(String::as_str).collect();
+                value_dropdown.set_model(Some(&gtk::StringList::new(&strings)));
+                let positions = choices.clone();
+                let value_dropdown_binding = variable
+                    .bind_property("value", &value_dropdown, "selected")
+                    .transform_to(move |_, text: String| {
+                        let position = positions.iter().position(|choice| *choice == text);
+                        Some(position.map_or(gtk::INVALID_LIST_POSITION, |p| p as u32))
+                    })
+                    .transform_from(move |_, selected: u32| choices.get(selected as usize).cloned())
+                    .bidirectional()
+                    .sync_create()
+                    .build();
+                bindings.push(value_dropdown_binding);
+            }
+            VariableKind::Object | VariableKind::List => {}
+        }
+    }
+
+    /// Flag the cell with the `error` style class while the value of `variable` is invalid.
+    fn update_error_class(&self, variable: &Variable) {
+        if variable.error().is_some() {
+            self.add_css_class("error");
+        } else {
+            self.remove_css_class("error");
+        }
     }
 
     pub fn unbind(&self) {
+        self.imp().variable.replace(None);
+        if let Some((variable, handler)) = self.imp().error_handler.take() {
+            variable.disconnect(handler);
+        }
         for binding in self.imp().bindings.borrow_mut().drain(..) {
             binding.unbind();
         }
     }
 }
 
+/// Return the first line of `text`, with an ellipsis if there are more.
+fn preview(text: &str) -> String {
+    let mut lines = text.lines();
+    let first = lines.next().unwrap_or_default();
+    if lines.next().is_some() {
+        format!("{} …", first)
+    } else {
+        first.to_string()
+    }
+}
+
 impl Default for VariableValueCell {
     fn default() -> Self {
         Self::new()
@@ -10,7 +10,27 @@ use gtk::{glib, subclass::prelude::*};
 mod imp {
     use super::*;
     use crate::variable::Variable;
-    use glib::types::StaticType;
+    use gtk::prelude::*;
+
+    /// Create a sorter comparing variables by `key`, ignoring case, keeping list items in order.
+    fn sorter_by<F>(key: F) -> gtk::CustomSorter
+    where
+        F: Fn(&Variable) -> String + 'static,
+    {
+        gtk::CustomSorter::new(move |a, b| {
+            let a = a
+                .downcast_ref::<Variable>()
+                .expect("Item must be a Variable");
+            let b = b
+                .downcast_ref::<Variable>()
+                .expect("Item must be a Variable");
+            if a.is_item() && b.is_item() {
+                a.index().cmp(&b.index()).into()
+            } else {
+                key(a).to_lowercase().cmp(&key(b).to_lowercase()).into()
+            }
+        })
+    }
 
     #[derive(Debug, gtk::CompositeTemplate)]
     #[template(resource = "/com/elebihan/TemplateFiller/ui/variables_view.ui")]
@@ -42,19 +62,9 @@ mod imp {
     impl ObjectImpl for VariablesView {
         fn constructed(&self) {
             self.parent_constructed();
-            let expression = gtk::PropertyExpression::new(
-                Variable::static_type(),
-                gtk::Expression::NONE,
-                "name",
-            );
-            let sorter = gtk::StringSorter::builder().expression(expression).build();
+            let sorter = sorter_by(|variable| variable.name());
             self.column_name.set_sorter(Some(&sorter));
-            let expression = gtk::PropertyExpression::new(
-                Variable::static_type(),
-                gtk::Expression::NONE,
-                "value",
-            );
-            let sorter = gtk::StringSorter::builder().expression(expression).build();
+            let sorter = sorter_by(|variable| variable.value());
             self.column_value.set_sorter(Some(&sorter));
             self.column_view
                 .sort_by_column(Some(&self.column_name), gtk::SortType::Ascending);
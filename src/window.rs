@@ -7,37 +7,38 @@
 //
 
 use crate::application::TemplateFiller;
-use crate::document::Document;
-use crate::variable::Variable;
-use crate::widgets::{VariableNameCell, VariableValueCell, VariablesView};
+use crate::batch::{self, Batch};
+use crate::data;
+use crate::document::{self, Document};
+use crate::settings::Settings;
+use crate::widgets::{BatchDialog, DocumentPage};
 use glib::clone;
 use gtk::{gio, glib, prelude::*, subclass::prelude::*};
-use std::{cell::RefCell, collections::HashMap, path::Path};
+use std::path::{Path, PathBuf};
 use tracing::{debug, error};
 
 mod imp {
     use super::*;
 
-    #[derive(Debug, gtk::CompositeTemplate)]
+    #[derive(Debug, Default, gtk::CompositeTemplate)]
     #[template(resource = "/com/elebihan/TemplateFiller/ui/window.ui")]
     pub struct Window {
         #[template_child]
         pub save_button: gtk::TemplateChild<gtk::Button>,
-        pub(crate) document: RefCell<Option<Document>>,
         #[template_child]
-        pub(crate) variables_view: gtk::TemplateChild<VariablesView>,
-        pub(crate) variables: RefCell<Option<gio::ListStore>>,
-    }
-
-    impl Default for Window {
-        fn default() -> Self {
-            Self {
-                save_button: gtk::TemplateChild::default(),
-                document: RefCell::new(None),
-                variables_view: gtk::TemplateChild::default(),
-                variables: RefCell::new(None),
-            }
-        }
+        pub(crate) stack: gtk::TemplateChild<gtk::Stack>,
+        #[template_child]
+        pub(crate) notebook: gtk::TemplateChild<gtk::Notebook>,
+        #[template_child]
+        pub(crate) recent_box: gtk::TemplateChild<gtk::Box>,
+        #[template_child]
+        pub(crate) recent_list: gtk::TemplateChild<gtk::ListBox>,
+        #[template_child]
+        pub(crate) recent_menu: gtk::TemplateChild<gio::Menu>,
+        #[template_child]
+        pub(crate) profile_button: gtk::TemplateChild<gtk::MenuButton>,
+        #[template_child]
+        pub(crate) profiles_section: gtk::TemplateChild<gio::Menu>,
     }
 
     #[glib::object_subclass]
@@ -52,13 +53,55 @@ mod imp {
                 debug!("win.open-document");
                 win.show_open_dialog()
             });
+            klass.install_action("win.open-directory", None, move |win, _, _| {
+                debug!("win.open-directory");
+                win.show_open_directory_dialog()
+            });
             klass.install_action("win.save-document", None, move |win, _, _| {
                 debug!("win.save-document");
                 win.show_save_dialog()
             });
+            klass.install_action("win.load-values", None, move |win, _, _| {
+                debug!("win.load-values");
+                win.show_load_values_dialog()
+            });
+            klass.install_action("win.export-values", None, move |win, _, _| {
+                debug!("win.export-values");
+                win.show_export_values_dialog()
+            });
+            klass.install_action("win.batch-render", None, move |win, _, _| {
+                debug!("win.batch-render");
+                win.show_records_dialog()
+            });
+            klass.install_action("win.reset-values", None, move |win, _, _| {
+                debug!("win.reset-values");
+                win.reset_values()
+            });
             klass.install_action("win.close-document", None, move |win, _, _| {
                 debug!("win.close-document");
-                win.close_document()
+                win.close_current_page()
+            });
+            klass.install_action(
+                "win.switch-profile",
+                Some(glib::VariantTy::STRING),
+                move |win, _, parameter| {
+                    debug!("win.switch-profile");
+                    if let Some(name) = parameter.and_then(String::from_variant) {
+                        win.switch_profile(&name)
+                    }
+                },
+            );
+            klass.install_action("win.save-profile", None, move |win, _, _| {
+                debug!("win.save-profile");
+                win.show_save_profile_dialog()
+            });
+            klass.install_action("win.rename-profile", None, move |win, _, _| {
+                debug!("win.rename-profile");
+                win.show_rename_profile_dialog()
+            });
+            klass.install_action("win.delete-profile", None, move |win, _, _| {
+                debug!("win.delete-profile");
+                win.show_delete_profile_dialog()
             });
         }
 
@@ -70,15 +113,57 @@ mod imp {
     impl ObjectImpl for Window {
         fn constructed(&self) {
             self.parent_constructed();
-            self.obj().setup_variables();
-            self.obj().setup_factories();
-            self.save_button.set_visible(false);
-            self.obj().action_set_enabled("win.save-document", false);
+            let obj = self.obj();
+            let action_remember_values = gio::ActionEntry::builder("remember-values")
+                .state(true.to_variant())
+                .activate(move |win: &super::Window, action, _| {
+                    let remember = !action
+                        .state()
+                        .and_then(|state| state.get::<bool>())
+                        .unwrap_or_default();
+                    action.set_state(&remember.to_variant());
+                    win.set_remember_values(remember);
+                })
+                .build();
+            obj.add_action_entries([action_remember_values]);
+            self.notebook.connect_notify_local(
+                Some("page"),
+                clone!(
+                    #[weak]
+                    obj,
+                    move |_, _| obj.update_page_state()
+                ),
+            );
+            obj.update_page_state();
         }
     }
 
     impl WidgetImpl for Window {}
-    impl WindowImpl for Window {}
+    impl WindowImpl for Window {
+        fn close_request(&self) -> glib::Propagation {
+            let obj = self.obj();
+            if obj.pages().iter().any(DocumentPage::modified) {
+                obj.confirm_close(
+                    "Close the window with unsaved changes?",
+                    clone!(
+                        #[weak]
+                        obj,
+                        move || {
+                            for page in obj.pages() {
+                                obj.close_page(&page);
+                            }
+                            obj.close();
+                        }
+                    ),
+                );
+                return glib::Propagation::Stop;
+            }
+            for page in obj.pages() {
+                obj.remember_values(&page);
+            }
+            self.parent_close_request()
+        }
+    }
     impl ApplicationWindowImpl for Window {}
 }
 
@@ -90,7 +175,65 @@ glib::wrapper! {
 
 impl Window {
     pub fn new(app: &TemplateFiller) -> Self {
-        glib::Object::builder().property("application", app).build()
+        let window: Self = glib::Object::builder().property("application", app).build();
+        window.set_recent_files(&app.recent_files());
+        window
+    }
+
+    /// Return the application of the window.
+    fn app(&self) -> Option<TemplateFiller> {
+        self.application().and_downcast::<TemplateFiller>()
+    }
+
+    /// Return the directory of the template opened last, to start browsing from.
+    fn recent_folder(&self) -> Option<gio::File> {
+        let files = self.app()?.recent_files();
+        files.first()?.parent().map(gio::File::for_path)
+    }
+
+    /// Show the templates opened recently in the menu and on the start page.
+    pub(crate) fn set_recent_files(&self, files: &[PathBuf]) {
+        let imp = self.imp();
+        imp.recent_menu.remove_all();
+        while let Some(row) = imp.recent_list.first_child() {
+            imp.recent_list.remove(&row);
+        }
+        for file in files {
+            let name = file
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let directory = file
+                .parent()
+                .map(|parent| parent.display().to_string())
+                .unwrap_or_default();
+            let item = gio::MenuItem::new(Some(&name), None);
+            item.set_action_and_target_value(Some("app.open-recent"), Some(&file.to_variant()));
+            imp.recent_menu.append_item(&item);
+            let content = gtk::Box::new(gtk::Orientation::Vertical, 0);
+            content.append(&gtk::Label::builder().label(&name).xalign(0.0).build());
+            content.append(
+                &gtk::Label::builder()
+                    .label(&directory)
+                    .xalign(0.0)
+                    .ellipsize(gtk::pango::EllipsizeMode::Middle)
+                    .css_classes(["dim-label"])
+                    .build(),
+            );
+            let button = gtk::Button::builder()
+                .child(&content)
+                .action_name("app.open-recent")
+                .action_target(&file.to_variant())
+                .tooltip_text(file.display().to_string())
+                .css_classes(["flat"])
+                .build();
+            imp.recent_list.append(&button);
+        }
+        if files.is_empty() {
+            imp.recent_menu
+                .append(Some("No recent templates"), None::<&str>);
+        }
+        imp.recent_box.set_visible(!files.is_empty());
     }
 
     pub fn show_open_dialog(&self) {
@@ -104,6 +247,43 @@ impl Window {
             .transient_for(self)
             .modal(true)
             .build();
+        if let Some(folder) = self.recent_folder() {
+            if let Err(error) = dialog.set_current_folder(Some(&folder)) {
+                debug!("Failed to browse {}: {}", folder.uri(), error);
+            }
+        }
+        dialog.add_buttons(&[
+            ("_Cancel", gtk::ResponseType::Cancel),
+            ("_Open", gtk::ResponseType::Accept),
+        ]);
+        dialog.connect_response(clone!(
+            #[weak(rename_to = win)]
+            self,
+            move |dialog, response| {
+                if response == gtk::ResponseType::Accept {
+                    if let Some(file) = dialog.file() {
+                        debug!("Opening {:?}", file.path());
+                        win.open_document(file)
+                    }
+                }
+                dialog.close();
+            }
+        ));
+        dialog.show();
+    }
+
+    pub fn show_open_directory_dialog(&self) {
+        let dialog = gtk::FileChooserDialog::builder()
+            .title("Select directory of Handlebars templates")
+            .action(gtk::FileChooserAction::SelectFolder)
+            .transient_for(self)
+            .modal(true)
+            .build();
+        if let Some(folder) = self.recent_folder() {
+            if let Err(error) = dialog.set_current_folder(Some(&folder)) {
+                debug!("Failed to browse {}: {}", folder.uri(), error);
+            }
+        }
         dialog.add_buttons(&[
             ("_Cancel", gtk::ResponseType::Cancel),
             ("_Open", gtk::ResponseType::Accept),
@@ -125,9 +305,18 @@ impl Window {
     }
 
     pub fn show_save_dialog(&self) {
+        let is_directory = self.current_page().is_some_and(|page| page.is_directory());
+        let (title, action) = if is_directory {
+            (
+                "Render templates into...",
+                gtk::FileChooserAction::SelectFolder,
+            )
+        } else {
+            ("Render template as...", gtk::FileChooserAction::Save)
+        };
         let dialog = gtk::FileChooserDialog::builder()
-            .title("Render template as...")
-            .action(gtk::FileChooserAction::Save)
+            .title(title)
+            .action(action)
             .transient_for(self)
             .modal(true)
             .build();
@@ -153,154 +342,624 @@ impl Window {
         dialog.show();
     }
 
+    pub fn show_load_values_dialog(&self) {
+        let filter = gtk::FileFilter::new();
+        for pattern in ["*.json", "*.yaml", "*.yml", "*.toml"] {
+            filter.add_pattern(pattern);
+        }
+        filter.set_name(Some("Data files"));
+        let dialog = gtk::FileChooserDialog::builder()
+            .title("Select values to load")
+            .action(gtk::FileChooserAction::Open)
+            .filter(&filter)
+            .transient_for(self)
+            .modal(true)
+            .build();
+        dialog.add_buttons(&[
+            ("_Cancel", gtk::ResponseType::Cancel),
+            ("_Open", gtk::ResponseType::Accept),
+        ]);
+        dialog.connect_response(clone!(
+            #[weak(rename_to = win)]
+            self,
+            move |dialog, response| {
+                dialog.close();
+                if response == gtk::ResponseType::Accept {
+                    if let Some(path) = dialog.file().and_then(|f| f.path()) {
+                        debug!("Loading values from {}", path.display());
+                        win.load_values(&path);
+                    }
+                }
+            }
+        ));
+        dialog.show();
+    }
+
+    pub fn show_export_values_dialog(&self) {
+        let filter = gtk::FileFilter::new();
+        for pattern in ["*.json", "*.yaml", "*.yml", "*.toml"] {
+            filter.add_pattern(pattern);
+        }
+        filter.set_name(Some("Data files"));
+        let dialog = gtk::FileChooserDialog::builder()
+            .title("Export values as...")
+            .action(gtk::FileChooserAction::Save)
+            .filter(&filter)
+            .transient_for(self)
+            .modal(true)
+            .build();
+        dialog.set_current_name("values.json");
+        dialog.add_buttons(&[
+            ("_Cancel", gtk::ResponseType::Cancel),
+            ("_Save", gtk::ResponseType::Accept),
+        ]);
+        dialog.connect_response(clone!(
+            #[weak(rename_to = win)]
+            self,
+            move |dialog, response| {
+                dialog.close();
+                if response == gtk::ResponseType::Accept {
+                    if let Some(path) = dialog.file().and_then(|f| f.path()) {
+                        debug!("Exporting values as {}", path.display());
+                        win.export_values(&path);
+                    }
+                }
+            }
+        ));
+        dialog.show();
+    }
+
+    pub fn show_records_dialog(&self) {
+        let filter = gtk::FileFilter::new();
+        for pattern in ["*.csv", "*.json", "*.yaml", "*.yml"] {
+            filter.add_pattern(pattern);
+        }
+        filter.set_name(Some("Records"));
+        let dialog = gtk::FileChooserDialog::builder()
+            .title("Select records to render")
+            .action(gtk::FileChooserAction::Open)
+            .filter(&filter)
+            .transient_for(self)
+            .modal(true)
+            .build();
+        dialog.add_buttons(&[
+            ("_Cancel", gtk::ResponseType::Cancel),
+            ("_Open", gtk::ResponseType::Accept),
+        ]);
+        dialog.connect_response(clone!(
+            #[weak(rename_to = win)]
+            self,
+            move |dialog, response| {
+                dialog.close();
+                if response == gtk::ResponseType::Accept {
+                    if let Some(path) = dialog.file().and_then(|file| file.path()) {
+                        debug!("Rendering records of {}", path.display());
+                        win.show_batch_dialog(&path);
+                    }
+                }
+            }
+        ));
+        dialog.show();
+    }
+
+    /// Show the dialog to render the current document once per record of the file at `path`.
+    fn show_batch_dialog(&self, path: &Path) {
+        let Some(page) = self.current_page() else {
+            return;
+        };
+        let Some(document) = page.document() else {
+            return;
+        };
+        let records = match batch::load_records(path) {
+            Ok(records) => records,
+            Err(error) => {
+                self.show_error("Failed to load records", &error);
+                return;
+            }
+        };
+        let batch = Batch::new(&document, records, page.collect_data());
+        if batch.is_empty() {
+            self.show_message(
+                gtk::MessageType::Warning,
+                "No records to render",
+                &path.display().to_string(),
+            );
+            return;
+        }
+        let dir = path.parent().unwrap_or(Path::new("."));
+        BatchDialog::new(self, document, batch, dir).present();
+    }
+
+    fn load_values(&self, path: &Path) {
+        let Some(page) = self.current_page() else {
+            return;
+        };
+        let data = match data::load(path) {
+            Ok(data) => data,
+            Err(error) => {
+                self.show_error("Failed to load values", &error);
+                return;
+            }
+        };
+        let unknown = page.load_values(&data);
+        if !unknown.is_empty() {
+            self.show_message(
+                gtk::MessageType::Warning,
+                "Some values are not used by the template",
+                &unknown.join("\n"),
+            );
+        }
+    }
+
+    /// Show a modal dialog with a message and its details.
+    fn show_message(&self, message_type: gtk::MessageType, text: &str, details: &str) {
+        let dialog = gtk::MessageDialog::builder()
+            .transient_for(self)
+            .modal(true)
+            .message_type(message_type)
+            .buttons(gtk::ButtonsType::Close)
+            .text(text)
+            .secondary_text(details)
+            .build();
+        dialog.connect_response(|dialog, _| dialog.close());
+        dialog.show();
+    }
+
+    /// Report `error` to the user, with its position in the template if known.
+    fn show_error(&self, text: &str, error: &document::Error) {
+        error!("{}: {}", text, error);
+        let details = match error.position() {
+            Some((line, column)) => {
+                format!("{}\n\nLine {}, column {}", error.summary(), line, column)
+            }
+            None => error.summary(),
+        };
+        self.show_message(gtk::MessageType::Error, text, &details);
+    }
+
+    /// Ask the user to confirm closing documents with unsaved changes, calling `close` if so.
+    fn confirm_close<F: Fn() + 'static>(&self, text: &str, close: F) {
+        self.confirm(
+            text,
+            "The values were changed since the template was last rendered.",
+            "_Close",
+            close,
+        );
+    }
+
+    /// Ask the user to confirm a destructive action labelled `label`, calling `accept` if so.
+    fn confirm<F: Fn() + 'static>(&self, text: &str, details: &str, label: &str, accept: F) {
+        let dialog = gtk::MessageDialog::builder()
+            .transient_for(self)
+            .modal(true)
+            .message_type(gtk::MessageType::Question)
+            .text(text)
+            .secondary_text(details)
+            .build();
+        dialog.add_buttons(&[
+            ("_Cancel", gtk::ResponseType::Cancel),
+            (label, gtk::ResponseType::Accept),
+        ]);
+        if let Some(button) = dialog.widget_for_response(gtk::ResponseType::Accept) {
+            button.add_css_class("destructive-action");
+        }
+        dialog.connect_response(move |dialog, response| {
+            dialog.close();
+            if response == gtk::ResponseType::Accept {
+                accept();
+            }
+        });
+        dialog.show();
+    }
+
+    /// Ask the user for the name of a profile, starting from `name`, calling `accept` with it.
+    fn ask_profile_name<F: Fn(&Self, &str) + 'static>(
+        &self,
+        text: &str,
+        name: &str,
+        label: &str,
+        accept: F,
+    ) {
+        let dialog = gtk::MessageDialog::builder()
+            .transient_for(self)
+            .modal(true)
+            .message_type(gtk::MessageType::Question)
+            .text(text)
+            .secondary_text("Profiles are named sets of values for the template.")
+            .build();
+        dialog.add_buttons(&[
+            ("_Cancel", gtk::ResponseType::Cancel),
+            (label, gtk::ResponseType::Accept),
+        ]);
+        dialog.set_default_response(gtk::ResponseType::Accept);
+        let entry = gtk::Entry::builder()
+            .text(name)
+            .activates_default(true)
+            .build();
+        if let Some(area) = dialog.message_area().downcast_ref::<gtk::Box>() {
+            area.append(&entry);
+        }
+        dialog.connect_response(clone!(
+            #[weak(rename_to = win)]
+            self,
+            #[weak]
+            entry,
+            move |dialog, response| {
+                dialog.close();
+                let name = entry.text().trim().to_string();
+                if response == gtk::ResponseType::Accept && !name.is_empty() {
+                    accept(&win, &name);
+                }
+            }
+        ));
+        dialog.show();
+    }
+
+    fn show_save_profile_dialog(&self) {
+        let Some(page) = self.current_page() else {
+            return;
+        };
+        self.ask_profile_name(
+            "Save the values as a profile",
+            &page.profile().unwrap_or_default(),
+            "_Save",
+            |win, name| win.save_profile(name),
+        );
+    }
+
+    fn show_rename_profile_dialog(&self) {
+        let Some(profile) = self.current_page().and_then(|page| page.profile()) else {
+            return;
+        };
+        self.ask_profile_name(
+            &format!("Rename the profile {}", profile),
+            &profile.clone(),
+            "_Rename",
+            move |win, name| win.rename_profile(&profile, name),
+        );
+    }
+
+    fn show_delete_profile_dialog(&self) {
+        let Some(profile) = self.current_page().and_then(|page| page.profile()) else {
+            return;
+        };
+        self.confirm(
+            &format!("Delete the profile {}?", profile),
+            "The values of the template are kept, but the profile can not be restored.",
+            "_Delete",
+            clone!(
+                #[weak(rename_to = win)]
+                self,
+                move || win.delete_profile(&profile)
+            ),
+        );
+    }
+
+    /// Return the pages of the open documents.
+    fn pages(&self) -> Vec<DocumentPage> {
+        let notebook = self.imp().notebook.get();
+        (0..notebook.n_pages())
+            .filter_map(|index| notebook.nth_page(Some(index)))
+            .filter_map(|child| child.downcast::<DocumentPage>().ok())
+            .collect()
+    }
+
+    /// Return the page of the document being edited, if any.
+    fn current_page(&self) -> Option<DocumentPage> {
+        let notebook = self.imp().notebook.get();
+        notebook
+            .nth_page(notebook.current_page())
+            .and_downcast::<DocumentPage>()
+    }
+
+    /// Open the template at `file` in a new tab, or show its tab if already open.
     pub(crate) fn open_document(&self, file: gio::File) {
-        match file
-            .path()
-            .ok_or_else(|| "Invalid file".to_string())
-            .and_then(|p| Document::open(p).map_err(|e| e.to_string()))
-        {
+        let Some(path) = file.path() else {
+            error!("open_document: {} is not a local file", file.uri());
+            self.show_message(
+                gtk::MessageType::Error,
+                "Failed to open template",
+                &format!("{} is not a local file", file.uri()),
+            );
+            return;
+        };
+        let notebook = self.imp().notebook.get();
+        if let Some(page) = self.pages().into_iter().find(|page| page.path() == path) {
+            notebook.set_current_page(notebook.page_num(&page));
+            return;
+        }
+        let partials_dirs = self.settings().partials_dirs;
+        match Document::open(&path, &partials_dirs) {
             Ok(document) => {
-                self.load_variables(&document);
-                let file_name = document.path().file_name().and_then(|n| n.to_str());
-                self.set_title(file_name);
-                *self.imp().document.borrow_mut() = Some(document);
-                self.imp().save_button.set_visible(true);
-                self.action_set_enabled("win.save-document", true)
+                debug!(
+                    "Variables: {}",
+                    document.variables().collect::<Vec<_>>().join(", ")
+                );
+                let page = DocumentPage::new(document, self.is_strict());
+                if let Some(data) = self.app().and_then(|app| app.saved_values(&path)) {
+                    debug!("Restoring values of {}", path.display());
+                    page.restore_values(&data);
+                }
+                if let Some(app) = self.app() {
+                    page.share_values(app.session(), true);
+                }
+                // Imported once linked, so that the shared values do not replace them.
+                let settings = self.settings();
+                if settings.import_env {
+                    debug!("Importing values from {}* variables", settings.env_prefix);
+                    if let Err(error) = page.import_env(&settings.env_prefix) {
+                        self.show_error("Failed to import values from the environment", &error);
+                    }
+                }
+                page.set_modified(false);
+                let index = notebook.append_page(&page, Some(&self.create_tab_label(&page)));
+                notebook.set_tab_reorderable(&page, true);
+                page.connect_notify_local(
+                    Some("complete"),
+                    clone!(
+                        #[weak(rename_to = win)]
+                        self,
+                        move |_, _| win.update_page_state()
+                    ),
+                );
+                notebook.set_current_page(Some(index));
+                self.update_page_state();
+                if let Some(app) = self.app() {
+                    app.add_recent_file(&path);
+                }
+            }
+            Err(error) => {
+                if let Some(app) = self.app() {
+                    app.prune_recent_files();
+                }
+                self.show_error("Failed to open template", &error)
+            }
+        }
+    }
+
+    /// Create the label of the tab of `page`, flagging unsaved changes, with a close button.
+    fn create_tab_label(&self, page: &DocumentPage) -> gtk::Box {
+        let title = page.title();
+        let label = gtk::Label::new(Some(&title));
+        page.bind_property("modified", &label, "label")
+            .transform_to(move |_, modified: bool| {
+                Some(if modified {
+                    format!("• {}", title)
+                } else {
+                    title.clone()
+                })
+            })
+            .sync_create()
+            .build();
+        let button = gtk::Button::builder()
+            .icon_name("window-close-symbolic")
+            .tooltip_text("Close template")
+            .css_classes(["flat", "circular"])
+            .build();
+        button.connect_clicked(clone!(
+            #[weak(rename_to = win)]
+            self,
+            #[weak]
+            page,
+            move |_| win.request_close_page(&page)
+        ));
+        let tab = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+        tab.set_tooltip_text(Some(&page.path().display().to_string()));
+        tab.append(&label);
+        tab.append(&button);
+        tab
+    }
+
+    fn close_current_page(&self) {
+        if let Some(page) = self.current_page() {
+            self.request_close_page(&page);
+        }
+    }
+
+    /// Close `page`, after confirmation if its values were changed since last rendered.
+    fn request_close_page(&self, page: &DocumentPage) {
+        if page.modified() {
+            self.confirm_close(
+                &format!("Close {} with unsaved changes?", page.title()),
+                clone!(
+                    #[weak(rename_to = win)]
+                    self,
+                    #[weak]
+                    page,
+                    move || win.close_page(&page)
+                ),
+            );
+        } else {
+            self.close_page(page);
+        }
+    }
+
+    fn close_page(&self, page: &DocumentPage) {
+        self.remember_values(page);
+        self.imp()
+            .notebook
+            .remove_page(self.imp().notebook.page_num(page));
+        self.update_page_state();
+    }
+
+    /// Update the title, the visible page and the actions for the current document.
+    fn update_page_state(&self) {
+        let page = self.current_page();
+        match &page {
+            Some(page) => self.set_title(Some(&page.title())),
+            None => self.set_title(Some("template-filler")),
+        }
+        let imp = self.imp();
+        imp.stack
+            .set_visible_child_name(if page.is_some() { "document" } else { "start" });
+        imp.save_button.set_visible(page.is_some());
+        self.action_set_enabled(
+            "win.save-document",
+            page.as_ref().is_some_and(DocumentPage::complete),
+        );
+        self.action_set_enabled("win.load-values", page.is_some());
+        self.action_set_enabled("win.export-values", page.is_some());
+        self.action_set_enabled("win.reset-values", page.is_some());
+        if let Some(action) = self
+            .lookup_action("remember-values")
+            .and_downcast::<gio::SimpleAction>()
+        {
+            let remember = page
+                .as_ref()
+                .zip(self.app())
+                .is_none_or(|(page, app)| app.remembers_values(&page.path()));
+            action.set_state(&remember.to_variant());
+            action.set_enabled(page.is_some());
+        }
+        self.action_set_enabled("win.batch-render", page.is_some());
+        self.action_set_enabled("win.close-document", page.is_some());
+        self.update_profiles();
+    }
+
+    /// Show the profiles of the current document in the profile switcher.
+    fn update_profiles(&self) {
+        let page = self.current_page();
+        let imp = self.imp();
+        imp.profiles_section.remove_all();
+        if let Some(page) = &page {
+            let names = page.profiles().names();
+            for name in &names {
+                let item = gio::MenuItem::new(Some(name), None);
+                item.set_action_and_target_value(
+                    Some("win.switch-profile"),
+                    Some(&name.to_variant()),
+                );
+                imp.profiles_section.append_item(&item);
+            }
+            if names.is_empty() {
+                imp.profiles_section
+                    .append(Some("No profiles"), None::<&str>);
+            }
+        }
+        let profile = page.as_ref().and_then(DocumentPage::profile);
+        imp.profile_button.set_visible(page.is_some());
+        imp.profile_button
+            .set_label(profile.as_deref().unwrap_or("No profile"));
+        self.action_set_enabled("win.save-profile", page.is_some());
+        self.action_set_enabled("win.rename-profile", profile.is_some());
+        self.action_set_enabled("win.delete-profile", profile.is_some());
+    }
+
+    /// Set the values of the current document to the ones of the profile `name`.
+    fn switch_profile(&self, name: &str) {
+        let Some(page) = self.current_page() else {
+            return;
+        };
+        match page.profiles().load(name) {
+            Ok(data) => {
+                page.load_values(&data);
+                page.set_profile(Some(name));
+                self.update_profiles();
             }
-            Err(error) => error!("open_document: {}", error),
+            Err(error) => self.show_error("Failed to load profile", &error),
         }
     }
 
-    fn close_document(&self) {
-        if self.imp().document.borrow().is_some() {
-            self.clear_variables();
-            self.set_title(Some("template-filler"));
-            *self.imp().document.borrow_mut() = None;
-            self.imp().save_button.set_visible(false);
-            self.action_set_enabled("win.save-document", false)
+    /// Save the values of the current document as the profile `name`.
+    fn save_profile(&self, name: &str) {
+        let Some(page) = self.current_page() else {
+            return;
+        };
+        match page.profiles().save(name, &page.collect_data()) {
+            Ok(()) => {
+                page.set_profile(Some(name));
+                self.update_profiles();
+            }
+            Err(error) => self.show_error("Failed to save profile", &error),
         }
     }
 
-    fn load_variables(&self, document: &Document) {
-        let variables = self.imp().variables.borrow();
-        if let Some(list_store) = variables.as_ref() {
-            list_store.remove_all();
-            for variable in document.variables() {
-                let variable = Variable::new(variable, "");
-                list_store.append(&variable);
+    fn rename_profile(&self, name: &str, new_name: &str) {
+        let Some(page) = self.current_page() else {
+            return;
+        };
+        match page.profiles().rename(name, new_name) {
+            Ok(()) => {
+                page.set_profile(Some(new_name));
+                self.update_profiles();
             }
+            Err(error) => self.show_error("Failed to rename profile", &error),
         }
     }
 
-    fn clear_variables(&self) {
-        if let Some(list_store) = self.imp().variables.borrow().as_ref() {
-            list_store.remove_all();
+    fn delete_profile(&self, name: &str) {
+        let Some(page) = self.current_page() else {
+            return;
+        };
+        match page.profiles().remove(name) {
+            Ok(()) => {
+                page.set_profile(None::<&str>);
+                self.update_profiles();
+            }
+            Err(error) => self.show_error("Failed to delete profile", &error),
         }
     }
 
+    /// Remember the values of `page`, to restore them when opening its template again.
+    fn remember_values(&self, page: &DocumentPage) {
+        if let Some(app) = self.app() {
+            app.save_values(&page.path(), page.collect_data());
+        }
+    }
+
+    /// Remember the values of the current document, or forget them for a sensitive template.
+    fn set_remember_values(&self, remember: bool) {
+        if let (Some(page), Some(app)) = (self.current_page(), self.app()) {
+            app.set_remember_values(&page.path(), remember);
+        }
+    }
+
+    /// Set the values of the current document back to their defaults, forgetting the saved ones.
+    fn reset_values(&self) {
+        if let Some(page) = self.current_page() {
+            page.reset_values();
+            page.set_profile(None::<&str>);
+            self.update_profiles();
+            if let Some(app) = self.app() {
+                // The defaults are not pushed to the other documents.
+                page.share_values(app.session(), false);
+                app.forget_values(&page.path());
+            }
+        }
+    }
+
+    /// Return the settings of the application.
+    fn settings(&self) -> Settings {
+        self.app().map(|app| app.settings()).unwrap_or_default()
+    }
+
+    /// Return `true` if strict rendering is enabled in the settings.
+    fn is_strict(&self) -> bool {
+        self.settings().strict
+    }
+
+    /// Enable or disable strict rendering in every tab, requiring a value for every variable.
+    pub(crate) fn set_strict(&self, strict: bool) {
+        for page in self.pages() {
+            page.set_strict(strict);
+        }
+        self.update_page_state();
+    }
+
     fn save_document(&self, path: &Path) {
-        if let Some(document) = self.imp().document.borrow().as_ref() {
-            if let Some(list_store) = self.imp().variables.borrow().as_ref() {
-                let data: HashMap<String, String> = list_store
-                    .iter()
-                    .filter_map(|item| {
-                        item.ok().and_then(|item: glib::Object| {
-                            item.downcast_ref::<Variable>()
-                                .map(|variable| (variable.name(), variable.value()))
-                        })
-                    })
-                    .collect();
-                if let Err(_err) = document.render_to_file(path, &data) {
-                    todo!()
-                }
+        if let Some(page) = self.current_page() {
+            match page.render(path) {
+                Ok(()) => self.remember_values(&page),
+                Err(error) => self.show_error("Failed to render template", &error),
             }
         }
     }
 
-    fn setup_variables(&self) {
-        let model = gio::ListStore::new::<Variable>();
-        let sorter = self
-            .imp()
-            .variables_view
-            .sorter()
-            .expect("VariablesView must have a Sorter");
-        let sorted_model = gtk::SortListModel::new(Some(model.clone()), Some(sorter));
-        self.imp().variables.replace(Some(model));
-        let selection_model = gtk::NoSelection::new(Some(sorted_model));
-        self.imp().variables_view.set_model(Some(&selection_model));
-    }
-
-    fn setup_factories(&self) {
-        let factory = gtk::SignalListItemFactory::new();
-        factory.connect_setup(move |_, list_item| {
-            let variable_cell = VariableNameCell::new();
-            list_item
-                .downcast_ref::<gtk::ListItem>()
-                .expect("VariableNameCell must be a ListItem")
-                .set_child(Some(&variable_cell));
-        });
-        factory.connect_bind(move |_, list_item| {
-            let variable = list_item
-                .downcast_ref::<gtk::ListItem>()
-                .expect("Needs to be a ListItem")
-                .item()
-                .and_downcast::<Variable>()
-                .expect("The item must be a Variable");
-            let cell = list_item
-                .downcast_ref::<gtk::ListItem>()
-                .expect("Needs to be a ListItem")
-                .child()
-                .and_downcast::<VariableNameCell>()
-                .expect("The child must be a VariableNameCell");
-            cell.bind(&variable);
-        });
-        factory.connect_unbind(move |_, list_item| {
-            let cell = list_item
-                .downcast_ref::<gtk::ListItem>()
-                .expect("Needs to be ListItem")
-                .child()
-                .and_downcast::<VariableNameCell>()
-                .expect("The child must be a VariableNameCell");
-            cell.unbind();
-        });
-        self.imp()
-            .variables_view
-            .set_name_column_factory(Some(&factory));
-
-        let factory = gtk::SignalListItemFactory::new();
-        factory.connect_setup(move |_, list_item| {
-            let variable_cell = VariableValueCell::new();
-            list_item
-                .downcast_ref::<gtk::ListItem>()
-                .expect("VariableValueCell must be a ListItem")
-                .set_child(Some(&variable_cell));
-        });
-        factory.connect_bind(move |_, list_item| {
-            let variable = list_item
-                .downcast_ref::<gtk::ListItem>()
-                .expect("Needs to be a ListItem")
-                .item()
-                .and_downcast::<Variable>()
-                .expect("The item must be a Variable");
-            let cell = list_item
-                .downcast_ref::<gtk::ListItem>()
-                .expect("Needs to be a ListItem")
-                .child()
-                .and_downcast::<VariableValueCell>()
-                .expect("The child must be a VariableValueCell");
-            cell.bind(&variable);
-        });
-        factory.connect_unbind(move |_, list_item| {
-            let cell = list_item
-                .downcast_ref::<gtk::ListItem>()
-                .expect("Needs to be ListItem")
-                .child()
-                .and_downcast::<VariableValueCell>()
-                .expect("The child must be a VariableValueCell");
-            cell.unbind();
-        });
-        self.imp()
-            .variables_view
-            .set_value_column_factory(Some(&factory));
+    fn export_values(&self, path: &Path) {
+        if let Some(page) = self.current_page() {
+            if let Err(error) = page.export_values(path) {
+                self.show_error("Failed to export values", &error);
+            }
+        }
     }
 }